@@ -1,4 +1,3 @@
-use image::RgbaImage;
 use std::{
     io::{BufReader, Read},
     path::Path,
@@ -14,23 +13,38 @@ use crate::{
     warn,
 };
 
-use super::internal_scale::InternalScale;
+use super::{
+    cell_width::AmbiguousWidth,
+    internal_scale::InternalScale,
+    output_format::{OutputError, OutputFormat},
+};
 
+#[allow(clippy::too_many_arguments)]
 pub fn make_image(
     output_path: &Path,
+    format: OutputFormat,
     font_family: FontFamily,
     png_width: Option<u32>,
+    font_size: f32,
+    line_height: f32,
+    letter_spacing: f32,
+    tab_width: usize,
+    ambiguous_width: AmbiguousWidth,
+    ligatures: bool,
+    embed_fonts: bool,
     input: &[u8],
     palette: Palette,
-) {
+) -> Result<(), OutputError> {
     // let  = FontFamily::default();
 
     let font = font_family.regular;
     let font_bold = font_family.bold;
     let font_italic = font_family.italic;
     let font_italic_bold = font_family.bold_italic;
+    let fallback_fonts = font_family.fallbacks;
+    let bitmap_font = font_family.bitmap;
 
-    let font_height = 50.0;
+    let font_height = font_size;
     let scale = InternalScale {
         x: font_height,
         y: font_height,
@@ -44,10 +58,18 @@ pub fn make_image(
         font_bold,
         font_italic,
         font_italic_bold,
+        fallback_fonts,
         font_height,
         scale,
+        line_height,
+        letter_spacing,
         palette,
         png_width,
+        tab_width,
+        ambiguous_width,
+        ligatures,
+        embed_fonts,
+        bitmap_font,
     });
     let reader = &mut BufReader::new(input);
     let mut buf = [0; 2048];
@@ -65,7 +87,5 @@ pub fn make_image(
         }
     }
 
-    let image: RgbaImage = performer.into();
-
-    image.save(output_path).unwrap();
+    format.write(performer, output_path)
 }
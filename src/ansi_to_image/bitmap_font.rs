@@ -0,0 +1,482 @@
+//! Bitmap fonts (PSF1, PSF2 and BDF), drawn pixel for pixel at integer
+//! scales like on a console.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    io::{self, Read},
+    path::Path,
+};
+
+use flate2::read::GzDecoder;
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_MODE_512: u8 = 0x01;
+const PSF1_MODE_HAS_TABLE: u8 = 0x06;
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xb5, 0x4a, 0x86];
+const PSF2_HAS_TABLE: usize = 0x01;
+/// Largest glyph dimension and offset accepted, in font pixels.
+const MAX_GLYPH_SIZE: i64 = 256;
+
+#[derive(Debug)]
+pub struct BitmapFont {
+    /// Cell size, in font pixels.
+    pub(super) width: usize,
+    pub(super) height: usize,
+    /// Rows above the baseline.
+    pub(super) ascent: usize,
+    glyphs: HashMap<char, BitmapGlyph>,
+}
+
+/// A glyph bitmap, placed in font pixels from the top left corner of the
+/// cell.
+#[derive(Debug)]
+pub(super) struct BitmapGlyph {
+    pub(super) left: i64,
+    pub(super) top: i64,
+    width: usize,
+    /// Row major, one entry per pixel.
+    pixels: Vec<bool>,
+}
+
+impl BitmapGlyph {
+    /// Positions of the set pixels, relative to the glyph's top left corner.
+    pub(super) fn set_pixels(&self) -> impl Iterator<Item = (i64, i64)> + '_ {
+        self.pixels
+            .iter()
+            .enumerate()
+            .filter(|(_, set)| **set)
+            .map(|(index, _)| ((index % self.width) as i64, (index / self.width) as i64))
+    }
+}
+
+#[derive(Debug)]
+pub enum BitmapFontError {
+    Io(io::Error),
+    Format(&'static str),
+}
+
+impl Display for BitmapFontError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            BitmapFontError::Io(err) => write!(f, "cannot read font file: {err}"),
+            BitmapFontError::Format(err) => write!(f, "invalid bitmap font: {err}"),
+        }
+    }
+}
+
+impl BitmapFont {
+    /// Loads a PSF1, PSF2 or BDF font, gzipped files are decompressed.
+    pub fn load(path: &Path) -> Result<Self, BitmapFontError> {
+        let mut data = std::fs::read(path).map_err(BitmapFontError::Io)?;
+        if data.starts_with(&[0x1f, 0x8b]) {
+            let mut decompressed = Vec::new();
+            GzDecoder::new(data.as_slice())
+                .read_to_end(&mut decompressed)
+                .map_err(BitmapFontError::Io)?;
+            data = decompressed;
+        }
+        Self::parse(&data)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, BitmapFontError> {
+        if data.starts_with(&PSF1_MAGIC) {
+            parse_psf1(data)
+        } else if data.starts_with(&PSF2_MAGIC) {
+            parse_psf2(data)
+        } else if data.starts_with(b"STARTFONT") {
+            parse_bdf(data)
+        } else {
+            Err(BitmapFontError::Format("not a PSF or BDF font"))
+        }
+    }
+
+    pub(super) fn glyph(&self, character: char) -> Option<&BitmapGlyph> {
+        self.glyphs.get(&character)
+    }
+}
+
+fn parse_psf1(data: &[u8]) -> Result<BitmapFont, BitmapFontError> {
+    let [_, _, mode, height, ..] = *data else {
+        return Err(BitmapFontError::Format("truncated PSF1 header"));
+    };
+    if height == 0 {
+        return Err(BitmapFontError::Format("invalid PSF1 glyph height"));
+    }
+    let count = if mode & PSF1_MODE_512 != 0 { 512 } else { 256 };
+    let height = height as usize;
+    let glyphs_end = 4 + count * height;
+    let bitmaps = data
+        .get(4..glyphs_end)
+        .ok_or(BitmapFontError::Format("truncated PSF1 glyphs"))?;
+
+    // The table lists UCS-2 characters of each glyph, ending with 0xFFFF;
+    // 0xFFFE starts character sequences, which are skipped
+    let mut table = vec![Vec::new(); count];
+    if mode & PSF1_MODE_HAS_TABLE != 0 {
+        let mut entries = data[glyphs_end..]
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]));
+        for characters in &mut table {
+            let mut in_sequence = false;
+            for entry in entries.by_ref() {
+                match entry {
+                    0xffff => break,
+                    0xfffe => in_sequence = true,
+                    _ if in_sequence => {}
+                    _ => characters.extend(char::from_u32(entry as u32)),
+                }
+            }
+        }
+    }
+    Ok(psf_font(8, height, bitmaps, table))
+}
+
+fn parse_psf2(data: &[u8]) -> Result<BitmapFont, BitmapFontError> {
+    let field = |index: usize| {
+        data.get(index * 4..index * 4 + 4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+            .ok_or(BitmapFontError::Format("truncated PSF2 header"))
+    };
+    let (header_size, flags, count) = (field(2)?, field(3)?, field(4)?);
+    let (glyph_size, height, width) = (field(5)?, field(6)?, field(7)?);
+    let max_size = MAX_GLYPH_SIZE as usize;
+    if !(1..=max_size).contains(&width)
+        || !(1..=max_size).contains(&height)
+        || glyph_size < width.div_ceil(8) * height
+    {
+        return Err(BitmapFontError::Format("invalid PSF2 glyph size"));
+    }
+    let glyphs_end = count
+        .checked_mul(glyph_size)
+        .and_then(|size| size.checked_add(header_size));
+    let bitmaps = glyphs_end
+        .and_then(|glyphs_end| data.get(header_size..glyphs_end))
+        .ok_or(BitmapFontError::Format("truncated PSF2 glyphs"))?;
+    let glyphs_end = header_size + bitmaps.len();
+
+    // The table lists UTF-8 characters of each glyph, ending with 0xFF;
+    // 0xFE starts character sequences, which are skipped
+    let mut table = vec![Vec::new(); count];
+    if flags & PSF2_HAS_TABLE != 0 {
+        let mut entries = data[glyphs_end..].split(|byte| *byte == 0xff);
+        for characters in &mut table {
+            let Some(entry) = entries.next() else {
+                break;
+            };
+            let singles = entry.split(|byte| *byte == 0xfe).next().unwrap_or_default();
+            characters.extend(String::from_utf8_lossy(singles).chars());
+        }
+    }
+    Ok(psf_font(width, height, bitmaps, table))
+}
+
+/// Builds a font of `width` × `height` cells out of glyphs stored one after
+/// the other, with rows padded to whole bytes and glyphs possibly padded
+/// after their last row. Fonts without a Unicode table use each glyph for
+/// the character of its index.
+fn psf_font(width: usize, height: usize, bitmaps: &[u8], table: Vec<Vec<char>>) -> BitmapFont {
+    let row_size = width.div_ceil(8);
+    let glyph_size = bitmaps.len() / table.len().max(1);
+    let has_table = table.iter().any(|characters| !characters.is_empty());
+    let mut glyphs = HashMap::new();
+    for (index, characters) in table.into_iter().enumerate() {
+        let rows = &bitmaps[index * glyph_size..][..row_size * height];
+        let pixels = (0..width * height)
+            .map(|pixel| {
+                let (x, y) = (pixel % width, pixel / width);
+                rows[y * row_size + x / 8] & (0x80 >> (x % 8)) != 0
+            })
+            .collect::<Vec<_>>();
+        let characters = if has_table {
+            characters
+        } else {
+            char::from_u32(index as u32).into_iter().collect()
+        };
+        for character in characters {
+            glyphs.entry(character).or_insert_with(|| BitmapGlyph {
+                left: 0,
+                top: 0,
+                width,
+                pixels: pixels.clone(),
+            });
+        }
+    }
+    BitmapFont {
+        width,
+        height,
+        // Console fonts leave about a quarter of the cell below the baseline
+        ascent: height - height / 4,
+        glyphs,
+    }
+}
+
+fn parse_bdf(data: &[u8]) -> Result<BitmapFont, BitmapFontError> {
+    let text = String::from_utf8_lossy(data);
+    let mut lines = text.lines().map(str::trim);
+    let numbers = |values: &str| -> Vec<i64> {
+        values
+            .split_whitespace()
+            .filter_map(|value| value.parse().ok())
+            .collect()
+    };
+
+    let mut bounding_box = None;
+    let (mut ascent, mut descent) = (None, None);
+    // Encoding, advance, bounding box and rows of each character
+    let mut characters = Vec::new();
+    while let Some(line) = lines.next() {
+        let (keyword, values) = line.split_once(' ').unwrap_or((line, ""));
+        match keyword {
+            "FONTBOUNDINGBOX" => bounding_box = Some(numbers(values)),
+            "FONT_ASCENT" => ascent = numbers(values).first().copied(),
+            "FONT_DESCENT" => descent = numbers(values).first().copied(),
+            "STARTCHAR" => {
+                let (mut encoding, mut advance, mut bbx) = (None, None, None);
+                let mut rows = Vec::new();
+                let mut in_bitmap = false;
+                for line in lines.by_ref() {
+                    let (keyword, values) = line.split_once(' ').unwrap_or((line, ""));
+                    match keyword {
+                        "ENDCHAR" => break,
+                        _ if in_bitmap => rows.push(line.to_string()),
+                        "ENCODING" => encoding = numbers(values).first().copied(),
+                        "DWIDTH" => advance = numbers(values).first().copied(),
+                        "BBX" => bbx = Some(numbers(values)),
+                        "BITMAP" => in_bitmap = true,
+                        _ => {}
+                    }
+                }
+                characters.push((encoding, advance, bbx, rows));
+            }
+            _ => {}
+        }
+    }
+
+    let bounding_box = bounding_box
+        .filter(|values| values.len() == 4)
+        .ok_or(BitmapFontError::Format("missing FONTBOUNDINGBOX"))?;
+    if !bdf_box_in_range(&bounding_box) {
+        return Err(BitmapFontError::Format("FONTBOUNDINGBOX out of range"));
+    }
+    let ascent = ascent.unwrap_or(bounding_box[1] + bounding_box[3]).max(0);
+    let descent = descent.unwrap_or(-bounding_box[3]).max(0);
+    if ascent > MAX_GLYPH_SIZE || descent > MAX_GLYPH_SIZE {
+        return Err(BitmapFontError::Format(
+            "FONT_ASCENT or FONT_DESCENT out of range",
+        ));
+    }
+    let height = (ascent + descent).max(1) as usize;
+
+    let mut glyphs = HashMap::new();
+    let mut width = 0;
+    for (encoding, advance, bbx, rows) in characters {
+        let Some(character) = encoding
+            .and_then(|encoding| u32::try_from(encoding).ok())
+            .and_then(char::from_u32)
+        else {
+            continue;
+        };
+        let bbx = bbx
+            .filter(|values| values.len() == 4)
+            .unwrap_or(bounding_box.clone());
+        if !bdf_box_in_range(&bbx) {
+            return Err(BitmapFontError::Format("BBX out of range"));
+        }
+        if advance.is_some_and(|advance| advance > MAX_GLYPH_SIZE) {
+            return Err(BitmapFontError::Format("DWIDTH out of range"));
+        }
+        let (glyph_width, glyph_height) = (bbx[0].max(0) as usize, bbx[1].max(0) as usize);
+        // The cell is as wide as the widest printable ASCII character
+        if (' '..='~').contains(&character) {
+            width = width.max(advance.unwrap_or(bbx[0]).max(0) as usize);
+        }
+        let pixels = (0..glyph_width * glyph_height)
+            .map(|pixel| {
+                let (x, y) = (pixel % glyph_width, pixel / glyph_width);
+                let digit = rows
+                    .get(y)
+                    .and_then(|row| row.as_bytes().get(x / 4))
+                    .and_then(|digit| (*digit as char).to_digit(16))
+                    .unwrap_or(0);
+                digit & (0x8 >> (x % 4)) != 0
+            })
+            .collect();
+        glyphs.insert(
+            character,
+            BitmapGlyph {
+                left: bbx[2],
+                top: ascent - bbx[1] - bbx[3],
+                width: glyph_width.max(1),
+                pixels,
+            },
+        );
+    }
+    if width == 0 {
+        width = bounding_box[0].max(1) as usize;
+    }
+    Ok(BitmapFont {
+        width,
+        height,
+        ascent: ascent as usize,
+        glyphs,
+    })
+}
+
+/// Whether the size and offset of a BDF bounding box, `[width, height, x, y]`,
+/// are within `MAX_GLYPH_SIZE`.
+fn bdf_box_in_range(values: &[i64]) -> bool {
+    let (size, offset) = values.split_at(2);
+    size.iter()
+        .all(|value| (0..=MAX_GLYPH_SIZE).contains(value))
+        && offset
+            .iter()
+            .all(|value| (-MAX_GLYPH_SIZE..=MAX_GLYPH_SIZE).contains(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format_error(data: &[u8]) -> &'static str {
+        match BitmapFont::parse(data) {
+            Err(BitmapFontError::Format(err)) => err,
+            result => panic!("expected a format error, got {result:?}"),
+        }
+    }
+
+    fn pixels(font: &BitmapFont, character: char) -> Vec<(i64, i64)> {
+        font.glyph(character).unwrap().set_pixels().collect()
+    }
+
+    /// A PSF1 font of 256 glyphs, 8 × 2, where only the first has pixels.
+    fn psf1(mode: u8, table: &[u16]) -> Vec<u8> {
+        let mut data = vec![0x36, 0x04, mode, 2, 0x80, 0x01];
+        data.resize(4 + 256 * 2, 0);
+        data.extend(table.iter().flat_map(|entry| entry.to_le_bytes()));
+        data
+    }
+
+    #[test]
+    fn psf1_unicode_table() {
+        // 'A' and the sequence 'B' 'C' for the first glyph, nothing after
+        let mut table = vec![0x41, 0xfffe, 0x42, 0x43, 0xffff];
+        table.resize(table.len() + 255, 0xffff);
+        let font = BitmapFont::parse(&psf1(0x02, &table)).unwrap();
+        assert_eq!((font.width, font.height), (8, 2));
+        assert_eq!(pixels(&font, 'A'), [(0, 0), (7, 1)]);
+        assert!(font.glyph('B').is_none());
+        assert!(font.glyph('\0').is_none());
+    }
+
+    #[test]
+    fn psf1_without_table_uses_glyph_indices() {
+        let font = BitmapFont::parse(&psf1(0, &[])).unwrap();
+        assert_eq!(pixels(&font, '\0'), [(0, 0), (7, 1)]);
+        assert!(font.glyph('ÿ').is_some());
+        assert!(font.glyph('Ā').is_none());
+    }
+
+    #[test]
+    fn psf1_malformed_headers() {
+        assert_eq!(
+            format_error(&[0x36, 0x04, 0, 0]),
+            "invalid PSF1 glyph height"
+        );
+        assert_eq!(format_error(&[0x36, 0x04, 0]), "truncated PSF1 header");
+        assert_eq!(
+            format_error(&[0x36, 0x04, 0, 16, 0]),
+            "truncated PSF1 glyphs"
+        );
+    }
+
+    fn psf2(count: u32, glyph_size: u32, height: u32, width: u32) -> Vec<u8> {
+        [
+            0x864ab572,
+            0,
+            32,
+            PSF2_HAS_TABLE as u32,
+            count,
+            glyph_size,
+            height,
+            width,
+        ]
+        .iter()
+        .flat_map(|field: &u32| field.to_le_bytes())
+        .collect()
+    }
+
+    #[test]
+    fn psf2_unicode_table() {
+        let mut data = psf2(2, 2, 2, 8);
+        data.extend([0x80, 0x01, 0xff, 0xff]);
+        // "é" and the sequence "xy" for the first glyph, "b" for the second
+        data.extend("é".as_bytes());
+        data.extend([0xfe, b'x', b'y', 0xff, b'b', 0xff]);
+        let font = BitmapFont::parse(&data).unwrap();
+        assert_eq!(pixels(&font, 'é'), [(0, 0), (7, 1)]);
+        assert_eq!(pixels(&font, 'b').len(), 16);
+        assert!(font.glyph('x').is_none());
+    }
+
+    #[test]
+    fn psf2_malformed_headers() {
+        assert_eq!(format_error(&psf2(1, 2, 2, 0)), "invalid PSF2 glyph size");
+        assert_eq!(
+            format_error(&psf2(1, 512, 2, 257)),
+            "invalid PSF2 glyph size"
+        );
+        assert_eq!(format_error(&psf2(1, 1, 2, 8)), "invalid PSF2 glyph size");
+        assert_eq!(
+            format_error(&psf2(u32::MAX, u32::MAX, 2, 8)),
+            "truncated PSF2 glyphs"
+        );
+        assert_eq!(
+            format_error(&psf2(1, 2, 2, 8)[..20]),
+            "truncated PSF2 header"
+        );
+    }
+
+    fn bdf(bounding_box: &str, bbx: &str) -> Vec<u8> {
+        format!(
+            "STARTFONT 2.1\n\
+             FONTBOUNDINGBOX {bounding_box}\n\
+             STARTPROPERTIES 2\nFONT_ASCENT 4\nFONT_DESCENT 2\nENDPROPERTIES\n\
+             CHARS 1\n\
+             STARTCHAR A\nENCODING 65\nDWIDTH 4 0\nBBX {bbx}\n\
+             BITMAP\n80\n40\nC0\nENDCHAR\n\
+             ENDFONT\n"
+        )
+        .into_bytes()
+    }
+
+    #[test]
+    fn bdf_glyph_offsets() {
+        let font = BitmapFont::parse(&bdf("4 6 0 -2", "2 3 1 -1")).unwrap();
+        assert_eq!((font.width, font.height, font.ascent), (4, 6, 4));
+        let glyph = font.glyph('A').unwrap();
+        // The box's bottom edge is one row below the baseline
+        assert_eq!((glyph.left, glyph.top), (1, 2));
+        assert_eq!(pixels(&font, 'A'), [(0, 0), (1, 1), (0, 2), (1, 2)]);
+    }
+
+    #[test]
+    fn bdf_out_of_range_values() {
+        assert_eq!(
+            format_error(&bdf("4 99999 0 -2", "2 3 1 -1")),
+            "FONTBOUNDINGBOX out of range"
+        );
+        assert_eq!(
+            format_error(&bdf("4 6 0 -2", "2 3 1000 -1")),
+            "BBX out of range"
+        );
+        assert_eq!(
+            format_error(&bdf("4 6 0 -2", "-2 3 1 -1")),
+            "BBX out of range"
+        );
+        assert_eq!(
+            format_error(&bdf("4 6 0", "2 3 1 -1")),
+            "missing FONTBOUNDINGBOX"
+        );
+    }
+}
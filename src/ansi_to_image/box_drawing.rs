@@ -0,0 +1,548 @@
+//! Geometric rendering of box drawing characters (U+2500–U+257F), block
+//! elements (U+2580–U+259F) and sextants (U+1FB00–U+1FB3B). They are drawn to
+//! the exact cell bounds, so lines and blocks join seamlessly across cells
+//! instead of depending on the font's glyph metrics.
+
+use std::ops::Range;
+
+use image::{Rgba, RgbaImage};
+
+use super::printer::blend_pixel;
+
+/// Pixel bounds of the cell a character is drawn in.
+pub(super) struct CellRect {
+    pub(super) left: f32,
+    pub(super) top: f32,
+    pub(super) right: f32,
+    pub(super) bottom: f32,
+}
+
+/// Whether `character` is drawn by [`draw_box_character`].
+pub(super) fn is_box_character(character: char) -> bool {
+    matches!(character as u32, 0x2500..=0x259f | 0x1fb00..=0x1fb3b)
+}
+
+/// Draws `character` geometrically if it is a box drawing, block element or
+/// sextant character, returns `false` for every other character. `light` is
+/// the thickness of light lines in pixels.
+pub(super) fn draw_box_character(
+    image: &mut RgbaImage,
+    character: char,
+    cell: &CellRect,
+    clip: &Range<f32>,
+    light: f32,
+    color: Rgba<u8>,
+) -> bool {
+    let mut canvas = Canvas {
+        image,
+        cell,
+        clip,
+        color,
+    };
+    let light = light.round().max(1.0);
+    match character as u32 {
+        0x2500..=0x257f => draw_line_character(&mut canvas, character as u32, light),
+        0x2580..=0x259f => draw_block_element(&mut canvas, character as u32),
+        0x1fb00..=0x1fb3b => draw_sextant(&mut canvas, character as u32),
+        _ => false,
+    }
+}
+
+struct Canvas<'a> {
+    image: &'a mut RgbaImage,
+    cell: &'a CellRect,
+    clip: &'a Range<f32>,
+    color: Rgba<u8>,
+}
+
+impl Canvas<'_> {
+    fn width(&self) -> f32 {
+        self.cell.right - self.cell.left
+    }
+
+    fn height(&self) -> f32 {
+        self.cell.bottom - self.cell.top
+    }
+
+    fn center(&self) -> (f32, f32) {
+        (
+            self.cell.left + (self.width() / 2.0).floor(),
+            self.cell.top + (self.height() / 2.0).floor(),
+        )
+    }
+
+    /// Fills a rectangle snapped to whole pixels and clamped to the cell.
+    fn fill_rect(&mut self, left: f32, top: f32, right: f32, bottom: f32, alpha: f32) {
+        let left = left.round().max(self.cell.left) as i64;
+        let right = right.round().min(self.cell.right) as i64;
+        let top = top.round().max(self.cell.top) as i64;
+        let bottom = bottom.round().min(self.cell.bottom) as i64;
+        for y in top..bottom {
+            if !self.clip.contains(&(y as f32)) {
+                continue;
+            }
+            for x in left..right {
+                blend_pixel(self.image, x, y, self.color, alpha);
+            }
+        }
+    }
+
+    /// Blends every pixel of the cell with the coverage `coverage` returns for
+    /// the pixel's center.
+    fn fill_with(&mut self, coverage: impl Fn(f32, f32) -> f32) {
+        for y in self.cell.top as i64..self.cell.bottom as i64 {
+            if !self.clip.contains(&(y as f32)) {
+                continue;
+            }
+            for x in self.cell.left as i64..self.cell.right as i64 {
+                let alpha = coverage(x as f32 + 0.5, y as f32 + 0.5).clamp(0.0, 1.0);
+                blend_pixel(self.image, x, y, self.color, alpha);
+            }
+        }
+    }
+
+    /// A horizontal band of `thickness` centered on `y`, from `left` to `right`.
+    fn horizontal(&mut self, left: f32, right: f32, y: f32, thickness: f32) {
+        let top = y - (thickness / 2.0).floor();
+        self.fill_rect(left, top, right, top + thickness, 1.0);
+    }
+
+    /// A vertical band of `thickness` centered on `x`, from `top` to `bottom`.
+    fn vertical(&mut self, top: f32, bottom: f32, x: f32, thickness: f32) {
+        let left = x - (thickness / 2.0).floor();
+        self.fill_rect(left, top, left + thickness, bottom, 1.0);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Weight {
+    None,
+    Light,
+    Heavy,
+    Double,
+}
+
+/// Arms of a line character as `[up, right, down, left]`.
+fn arms(code: u32) -> Option<[Weight; 4]> {
+    let arms = match code {
+        0x2500 => "0101",
+        0x2501 => "0202",
+        0x2502 => "1010",
+        0x2503 => "2020",
+        0x250c => "0110",
+        0x250d => "0210",
+        0x250e => "0120",
+        0x250f => "0220",
+        0x2510 => "0011",
+        0x2511 => "0012",
+        0x2512 => "0021",
+        0x2513 => "0022",
+        0x2514 => "1100",
+        0x2515 => "1200",
+        0x2516 => "2100",
+        0x2517 => "2200",
+        0x2518 => "1001",
+        0x2519 => "1002",
+        0x251a => "2001",
+        0x251b => "2002",
+        0x251c => "1110",
+        0x251d => "1210",
+        0x251e => "2110",
+        0x251f => "1120",
+        0x2520 => "2120",
+        0x2521 => "2210",
+        0x2522 => "1220",
+        0x2523 => "2220",
+        0x2524 => "1011",
+        0x2525 => "1012",
+        0x2526 => "2011",
+        0x2527 => "1021",
+        0x2528 => "2021",
+        0x2529 => "2012",
+        0x252a => "1022",
+        0x252b => "2022",
+        0x252c => "0111",
+        0x252d => "0112",
+        0x252e => "0211",
+        0x252f => "0212",
+        0x2530 => "0121",
+        0x2531 => "0122",
+        0x2532 => "0221",
+        0x2533 => "0222",
+        0x2534 => "1101",
+        0x2535 => "1102",
+        0x2536 => "1201",
+        0x2537 => "1202",
+        0x2538 => "2101",
+        0x2539 => "2102",
+        0x253a => "2201",
+        0x253b => "2202",
+        0x253c => "1111",
+        0x253d => "1112",
+        0x253e => "1211",
+        0x253f => "1212",
+        0x2540 => "2111",
+        0x2541 => "1121",
+        0x2542 => "2121",
+        0x2543 => "2112",
+        0x2544 => "2211",
+        0x2545 => "1122",
+        0x2546 => "1221",
+        0x2547 => "2212",
+        0x2548 => "1222",
+        0x2549 => "2122",
+        0x254a => "2221",
+        0x254b => "2222",
+        0x2550 => "0303",
+        0x2551 => "3030",
+        0x2552 => "0310",
+        0x2553 => "0130",
+        0x2554 => "0330",
+        0x2555 => "0013",
+        0x2556 => "0031",
+        0x2557 => "0033",
+        0x2558 => "1300",
+        0x2559 => "3100",
+        0x255a => "3300",
+        0x255b => "1003",
+        0x255c => "3001",
+        0x255d => "3003",
+        0x255e => "1310",
+        0x255f => "3130",
+        0x2560 => "3330",
+        0x2561 => "1013",
+        0x2562 => "3031",
+        0x2563 => "3033",
+        0x2564 => "0313",
+        0x2565 => "0131",
+        0x2566 => "0333",
+        0x2567 => "1303",
+        0x2568 => "3101",
+        0x2569 => "3303",
+        0x256a => "1313",
+        0x256b => "3131",
+        0x256c => "3333",
+        0x2574 => "0001",
+        0x2575 => "1000",
+        0x2576 => "0100",
+        0x2577 => "0010",
+        0x2578 => "0002",
+        0x2579 => "2000",
+        0x257a => "0200",
+        0x257b => "0020",
+        0x257c => "0201",
+        0x257d => "1020",
+        0x257e => "0102",
+        0x257f => "2010",
+        _ => return None,
+    };
+    let mut weights = [Weight::None; 4];
+    for (weight, digit) in weights.iter_mut().zip(arms.bytes()) {
+        *weight = match digit {
+            b'1' => Weight::Light,
+            b'2' => Weight::Heavy,
+            b'3' => Weight::Double,
+            _ => Weight::None,
+        };
+    }
+    Some(weights)
+}
+
+fn draw_line_character(canvas: &mut Canvas, code: u32, light: f32) -> bool {
+    match code {
+        // Dashed lines, with 3, 4 or 2 dashes per cell
+        0x2504..=0x250b | 0x254c..=0x254f => {
+            let (dashes, offset) = match code {
+                0x2504..=0x2507 => (3, code - 0x2504),
+                0x2508..=0x250b => (4, code - 0x2508),
+                _ => (2, code - 0x254c),
+            };
+            let thickness = match offset % 2 {
+                0 => light,
+                _ => light * 2.0,
+            };
+            draw_dashes(canvas, dashes, offset >= 2, thickness);
+            true
+        }
+        0x256d..=0x2570 => {
+            draw_arc(canvas, code, light);
+            true
+        }
+        0x2571..=0x2573 => {
+            draw_diagonals(canvas, code, light);
+            true
+        }
+        _ => match arms(code) {
+            Some(arms) => {
+                draw_arms(canvas, arms, light);
+                true
+            }
+            None => false,
+        },
+    }
+}
+
+/// Draws straight arms from the cell edges to the center. Double lines are
+/// two light lines, their inner lines stop at perpendicular double lines so
+/// corners and junctions are drawn as in the font's glyphs.
+fn draw_arms(canvas: &mut Canvas, [up, right, down, left]: [Weight; 4], light: f32) {
+    let (cx, cy) = canvas.center();
+    let cell = (
+        canvas.cell.left,
+        canvas.cell.top,
+        canvas.cell.right,
+        canvas.cell.bottom,
+    );
+    let gap = light;
+    let half_extent = |weight: Weight| match weight {
+        Weight::None => 0.0,
+        Weight::Light => light / 2.0,
+        Weight::Heavy => light,
+        Weight::Double => gap + light / 2.0,
+    };
+    let vertical_half = half_extent(up).max(half_extent(down));
+    let horizontal_half = half_extent(left).max(half_extent(right));
+    let vertical_double = up == Weight::Double || down == Weight::Double;
+    let horizontal_double = left == Weight::Double || right == Weight::Double;
+
+    // How far an arm reaches past the center, negative values stop before it
+    let single_reach = |own: Weight, opposite: Weight, perpendicular_double: bool, across: f32| {
+        if perpendicular_double && opposite == Weight::None {
+            -gap
+        } else {
+            across.max(half_extent(own))
+        }
+    };
+    let double_reach = |perpendicular: Weight, perpendicular_double: bool, across: f32| match (
+        perpendicular_double,
+        perpendicular,
+    ) {
+        (true, Weight::Double) => -(gap - light / 2.0),
+        (true, _) => gap + light / 2.0,
+        (false, _) if across > 0.0 => across,
+        (false, _) => light / 2.0,
+    };
+
+    for (weight, opposite, sign) in [(left, right, -1.0), (right, left, 1.0)] {
+        let span = |reach: f32| {
+            if sign < 0.0 {
+                (cell.0, cx + reach)
+            } else {
+                (cx - reach, cell.2)
+            }
+        };
+        match weight {
+            Weight::None => {}
+            Weight::Light | Weight::Heavy => {
+                let reach = single_reach(weight, opposite, vertical_double, vertical_half);
+                let (from, to) = span(reach);
+                canvas.horizontal(from, to, cy, half_extent(weight) * 2.0);
+            }
+            Weight::Double => {
+                let (from, to) = span(double_reach(up, vertical_double, vertical_half));
+                canvas.horizontal(from, to, cy - gap, light);
+                let (from, to) = span(double_reach(down, vertical_double, vertical_half));
+                canvas.horizontal(from, to, cy + gap, light);
+            }
+        }
+    }
+
+    for (weight, opposite, sign) in [(up, down, -1.0), (down, up, 1.0)] {
+        let span = |reach: f32| {
+            if sign < 0.0 {
+                (cell.1, cy + reach)
+            } else {
+                (cy - reach, cell.3)
+            }
+        };
+        match weight {
+            Weight::None => {}
+            Weight::Light | Weight::Heavy => {
+                let reach = single_reach(weight, opposite, horizontal_double, horizontal_half);
+                let (from, to) = span(reach);
+                canvas.vertical(from, to, cx, half_extent(weight) * 2.0);
+            }
+            Weight::Double => {
+                let (from, to) = span(double_reach(left, horizontal_double, horizontal_half));
+                canvas.vertical(from, to, cx - gap, light);
+                let (from, to) = span(double_reach(right, horizontal_double, horizontal_half));
+                canvas.vertical(from, to, cx + gap, light);
+            }
+        }
+    }
+}
+
+/// Draws `count` evenly spaced dashes across the cell.
+fn draw_dashes(canvas: &mut Canvas, count: u32, vertical: bool, thickness: f32) {
+    let (cx, cy) = canvas.center();
+    let (start, length) = if vertical {
+        (canvas.cell.top, canvas.height())
+    } else {
+        (canvas.cell.left, canvas.width())
+    };
+    let slot = length / count as f32;
+    for dash in 0..count {
+        let from = start + slot * dash as f32;
+        let to = from + slot * 0.6;
+        if vertical {
+            canvas.vertical(from, to, cx, thickness);
+        } else {
+            canvas.horizontal(from, to, cy, thickness);
+        }
+    }
+}
+
+/// Draws a rounded corner, a quarter circle joined to the cell edges by
+/// straight lines.
+fn draw_arc(canvas: &mut Canvas, code: u32, light: f32) {
+    let (cx, cy) = canvas.center();
+    // Direction of the horizontal and vertical arms
+    let (sx, sy) = match code {
+        0x256d => (1.0, 1.0),
+        0x256e => (-1.0, 1.0),
+        0x256f => (-1.0, -1.0),
+        _ => (1.0, -1.0),
+    };
+    let radius = (canvas.width() / 2.0).min(canvas.height() / 2.0).floor();
+    let (center_x, center_y) = (cx + sx * radius, cy + sy * radius);
+    // The bands are centered half a pixel off when their thickness is odd
+    let offset = match light as u32 % 2 {
+        1 => 0.5,
+        _ => 0.0,
+    };
+
+    if sx > 0.0 {
+        canvas.horizontal(center_x, canvas.cell.right, cy, light);
+    } else {
+        canvas.horizontal(canvas.cell.left, center_x, cy, light);
+    }
+    if sy > 0.0 {
+        canvas.vertical(center_y, canvas.cell.bottom, cx, light);
+    } else {
+        canvas.vertical(canvas.cell.top, center_y, cx, light);
+    }
+    let (center_x, center_y) = (center_x + offset, center_y + offset);
+    canvas.fill_with(|x, y| {
+        if (x - center_x) * sx > 0.0 || (y - center_y) * sy > 0.0 {
+            return 0.0;
+        }
+        let distance = (x - center_x).hypot(y - center_y);
+        light / 2.0 + 0.5 - (distance - radius).abs()
+    });
+}
+
+/// Draws the diagonals of `╱`, `╲` and `╳` from corner to corner.
+fn draw_diagonals(canvas: &mut Canvas, code: u32, light: f32) {
+    let cell = canvas.cell;
+    let (left, top, right, bottom) = (cell.left, cell.top, cell.right, cell.bottom);
+    let rising = code == 0x2571 || code == 0x2573;
+    let falling = code == 0x2572 || code == 0x2573;
+    let (width, height) = (right - left, bottom - top);
+    let length = width.hypot(height);
+    canvas.fill_with(|x, y| {
+        let mut coverage: f32 = 0.0;
+        if rising {
+            let distance = ((x - left) * height + (y - bottom) * width).abs() / length;
+            coverage = coverage.max(light / 2.0 + 0.5 - distance);
+        }
+        if falling {
+            let distance = ((x - left) * height - (y - top) * width).abs() / length;
+            coverage = coverage.max(light / 2.0 + 0.5 - distance);
+        }
+        coverage
+    });
+}
+
+fn draw_block_element(canvas: &mut Canvas, code: u32) -> bool {
+    let cell = canvas.cell;
+    let (left, top, right, bottom) = (cell.left, cell.top, cell.right, cell.bottom);
+    let (width, height) = (canvas.width(), canvas.height());
+    let (mid_x, mid_y) = (left + (width / 2.0).round(), top + (height / 2.0).round());
+    let eighth = |eighths: u32, length: f32| (length * eighths as f32 / 8.0).round();
+
+    match code {
+        // ▀
+        0x2580 => canvas.fill_rect(left, top, right, mid_y, 1.0),
+        // ▁ to █, lower eighths
+        0x2581..=0x2588 => {
+            let block = eighth(code - 0x2580, height);
+            canvas.fill_rect(left, bottom - block, right, bottom, 1.0)
+        }
+        // ▉ to ▏, left eighths
+        0x2589..=0x258f => {
+            let block = eighth(0x2590 - code, width);
+            canvas.fill_rect(left, top, left + block, bottom, 1.0)
+        }
+        // ▐
+        0x2590 => canvas.fill_rect(mid_x, top, right, bottom, 1.0),
+        // ░ ▒ ▓
+        0x2591..=0x2593 => {
+            let alpha = (code - 0x2590) as f32 / 4.0;
+            canvas.fill_rect(left, top, right, bottom, alpha)
+        }
+        // ▔
+        0x2594 => canvas.fill_rect(left, top, right, top + eighth(1, height), 1.0),
+        // ▕
+        0x2595 => canvas.fill_rect(right - eighth(1, width), top, right, bottom, 1.0),
+        // Quadrants, bits are upper left, upper right, lower left and lower right
+        0x2596..=0x259f => {
+            let quadrants = match code {
+                0x2596 => 0b0010,
+                0x2597 => 0b0001,
+                0x2598 => 0b1000,
+                0x2599 => 0b1011,
+                0x259a => 0b1001,
+                0x259b => 0b1110,
+                0x259c => 0b1101,
+                0x259d => 0b0100,
+                0x259e => 0b0110,
+                _ => 0b0111,
+            };
+            let rects = [
+                (left, top, mid_x, mid_y),
+                (mid_x, top, right, mid_y),
+                (left, mid_y, mid_x, bottom),
+                (mid_x, mid_y, right, bottom),
+            ];
+            for (index, (x0, y0, x1, y1)) in rects.into_iter().enumerate() {
+                if quadrants & (0b1000 >> index) != 0 {
+                    canvas.fill_rect(x0, y0, x1, y1, 1.0);
+                }
+            }
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// Sextants split the cell in two columns and three rows. The block skips the
+/// patterns that already exist as half blocks (`▌`, `▐`) or are empty or full.
+fn draw_sextant(canvas: &mut Canvas, code: u32) -> bool {
+    let mut pattern = code - 0x1fb00 + 1;
+    if pattern >= 21 {
+        pattern += 1;
+    }
+    if pattern >= 42 {
+        pattern += 1;
+    }
+    let cell = canvas.cell;
+    let mid_x = cell.left + (canvas.width() / 2.0).round();
+    let rows = [
+        cell.top,
+        cell.top + (canvas.height() / 3.0).round(),
+        cell.top + (canvas.height() * 2.0 / 3.0).round(),
+        cell.bottom,
+    ];
+    for sextant in 0..6 {
+        if pattern & (1 << sextant) == 0 {
+            continue;
+        }
+        let (left, right) = match sextant % 2 {
+            0 => (cell.left, mid_x),
+            _ => (mid_x, cell.right),
+        };
+        let row = sextant / 2;
+        canvas.fill_rect(left, rows[row], right, rows[row + 1], 1.0);
+    }
+    true
+}
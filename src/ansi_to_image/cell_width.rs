@@ -0,0 +1,43 @@
+use unicode_width::UnicodeWidthChar;
+
+pub(super) const ZERO_WIDTH_JOINER: char = '\u{200d}';
+pub(super) const VARIATION_SELECTOR_16: char = '\u{fe0f}';
+
+/// Skin tone modifiers, which extend the emoji before them.
+pub(super) fn is_emoji_modifier(character: char) -> bool {
+    ('\u{1f3fb}'..='\u{1f3ff}').contains(&character)
+}
+
+/// Regional indicator symbols, a pair of them is a flag.
+pub(super) fn is_regional_indicator(character: char) -> bool {
+    ('\u{1f1e6}'..='\u{1f1ff}').contains(&character)
+}
+
+/// Width of East Asian Ambiguous characters (Greek and Cyrillic letters, `±`,
+/// `§`, ...), which terminals in CJK locales draw as wide.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(super) enum AmbiguousWidth {
+    #[default]
+    Narrow,
+    Wide,
+}
+
+impl AmbiguousWidth {
+    pub(super) fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "narrow" | "1" => Some(Self::Narrow),
+            "wide" | "2" => Some(Self::Wide),
+            _ => None,
+        }
+    }
+
+    /// Number of cells `character` occupies, zero for combining marks and
+    /// other characters that attach to the previous one.
+    pub(super) fn char_width(self, character: char) -> usize {
+        let width = match self {
+            Self::Narrow => character.width(),
+            Self::Wide => character.width_cjk(),
+        };
+        width.unwrap_or(0)
+    }
+}
@@ -0,0 +1,67 @@
+/// Character sets that can be designated to G0 and G1 with `ESC ( <final>`
+/// and `ESC ) <final>`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(super) enum Charset {
+    #[default]
+    Ascii,
+    /// `ESC ( A`, replaces `#` with the pound sign
+    Uk,
+    /// `ESC ( 0`, maps `_` to `~` to line drawing and other symbols
+    DecSpecialGraphics,
+}
+
+impl Charset {
+    pub(super) fn from_final_byte(byte: u8) -> Option<Self> {
+        match byte {
+            b'B' => Some(Self::Ascii),
+            b'A' => Some(Self::Uk),
+            b'0' => Some(Self::DecSpecialGraphics),
+            _ => None,
+        }
+    }
+
+    pub(super) fn map(self, character: char) -> char {
+        match self {
+            Self::Ascii => character,
+            Self::Uk => match character {
+                '#' => '£',
+                character => character,
+            },
+            Self::DecSpecialGraphics => match character {
+                '_' => '\u{a0}',
+                '`' => '◆',
+                'a' => '▒',
+                'b' => '␉',
+                'c' => '␌',
+                'd' => '␍',
+                'e' => '␊',
+                'f' => '°',
+                'g' => '±',
+                'h' => '␤',
+                'i' => '␋',
+                'j' => '┘',
+                'k' => '┐',
+                'l' => '┌',
+                'm' => '└',
+                'n' => '┼',
+                'o' => '⎺',
+                'p' => '⎻',
+                'q' => '─',
+                'r' => '⎼',
+                's' => '⎽',
+                't' => '├',
+                'u' => '┤',
+                'v' => '┴',
+                'w' => '┬',
+                'x' => '│',
+                'y' => '≤',
+                'z' => '≥',
+                '{' => 'π',
+                '|' => '≠',
+                '}' => '£',
+                '~' => '·',
+                character => character,
+            },
+        }
+    }
+}
@@ -1,4 +1,4 @@
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub(super) enum ColorType {
     PrimaryForeground,
     PrimaryBackground,
@@ -8,7 +8,7 @@ pub(super) enum ColorType {
     Rgb { field1: (u8, u8, u8) },
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub(super) enum Color {
     Black,
     Red,
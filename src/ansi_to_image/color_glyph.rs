@@ -0,0 +1,546 @@
+//! Color glyphs, drawn from the `COLR`/`CPAL` tables or from embedded
+//! bitmaps (`CBDT`, `sbix`) instead of the glyph outline.
+
+use ab_glyph::{point, Font, GlyphId, GlyphImageFormat, Point, ScaleFont};
+use ab_glyph_rasterizer::Rasterizer;
+use image::{imageops, ImageFormat, Rgba, RgbaImage};
+use ttf_parser::{
+    colr::{ClipBox, ColorStop, CompositeMode, GradientExtend, Paint, Painter},
+    Face, OutlineBuilder, RgbaColor, Transform,
+};
+
+use crate::trace;
+
+use super::{font_cache::FontFace, internal_scale::InternalScale};
+
+/// Pixel area a glyph is drawn in.
+pub(super) struct GlyphBox {
+    /// Left end of the baseline.
+    pub(super) origin: (f32, f32),
+    /// Top of the cells the glyph covers.
+    pub(super) top: f32,
+    pub(super) width: f32,
+    pub(super) height: f32,
+}
+
+/// A rendered color glyph, `left` and `top` are the position of the image's
+/// top left corner.
+pub(super) struct ColorGlyph {
+    pub(super) image: RgbaImage,
+    pub(super) left: i64,
+    pub(super) top: i64,
+}
+
+/// Whether `font` draws `character` in color.
+pub(super) fn has_color_glyph(font: &FontFace, character: char) -> bool {
+    let id = font.glyph_id(character);
+    if id.0 == 0 {
+        return false;
+    }
+    let colr = font
+        .face()
+        .is_some_and(|face| face.is_color_glyph(ttf_parser::GlyphId(id.0)));
+    colr || font
+        .glyph_raster_image2(id, u16::MAX)
+        .is_some_and(|raster| is_color_format(&raster.format))
+}
+
+/// Renders `character` in color, or returns `None` when `font` only has an
+/// outline for it. `foreground` is used by layers painted in the text color.
+pub(super) fn render_color_glyph(
+    font: &FontFace,
+    character: char,
+    scale: InternalScale,
+    area: &GlyphBox,
+    foreground: Rgba<u8>,
+) -> Option<ColorGlyph> {
+    let id = font.glyph_id(character);
+    if id.0 == 0 {
+        return None;
+    }
+    render_colr(font, id, scale, area, foreground).or_else(|| render_bitmap(font, id, area))
+}
+
+fn is_color_format(format: &GlyphImageFormat) -> bool {
+    matches!(
+        format,
+        GlyphImageFormat::Png | GlyphImageFormat::BitmapPremulBgra32
+    )
+}
+
+/// Paints the `COLR` layers of the glyph, scaled like the outlines of the
+/// font and shrunk when its advance is wider than the cells.
+fn render_colr(
+    font: &FontFace,
+    id: GlyphId,
+    scale: InternalScale,
+    area: &GlyphBox,
+    foreground: Rgba<u8>,
+) -> Option<ColorGlyph> {
+    let face = font.face()?;
+    let glyph_id = ttf_parser::GlyphId(id.0);
+    if !face.is_color_glyph(glyph_id) {
+        return None;
+    }
+
+    let scaled = font.as_scaled(scale);
+    let advance = scaled.h_advance(id);
+    let fit = if advance > area.width && advance > 0.0 {
+        area.width / advance
+    } else {
+        1.0
+    };
+    let left = area.origin.0.floor();
+    let top = area.top.floor();
+    let width = (area.origin.0 + area.width - left).ceil().max(1.0) as usize;
+    let height = (area.top + area.height - top).ceil().max(1.0) as usize;
+
+    // Font units, y up, to canvas pixels, y down
+    let transform = Transform::new(
+        scaled.h_scale_factor() * fit,
+        0.0,
+        0.0,
+        -scaled.v_scale_factor() * fit,
+        area.origin.0 - left,
+        area.origin.1 - top,
+    );
+    let mut painter = CanvasPainter::new(face, width, height, transform);
+    let Rgba([red, green, blue, alpha]) = foreground;
+    face.paint_color_glyph(
+        glyph_id,
+        0,
+        RgbaColor::new(red, green, blue, alpha),
+        &mut painter,
+    )?;
+
+    Some(ColorGlyph {
+        image: painter.into_image(),
+        left: left as i64,
+        top: top as i64,
+    })
+}
+
+/// Scales the embedded bitmap closest to the cell height to fit the cells,
+/// keeping its aspect ratio.
+fn render_bitmap(font: &FontFace, id: GlyphId, area: &GlyphBox) -> Option<ColorGlyph> {
+    let pixel_size = area.height.round().clamp(1.0, u16::MAX as f32) as u16;
+    let raster = font.glyph_raster_image2(id, pixel_size)?;
+    let bitmap = match raster.format {
+        GlyphImageFormat::Png => image::load_from_memory_with_format(raster.data, ImageFormat::Png)
+            .ok()?
+            .into_rgba8(),
+        GlyphImageFormat::BitmapPremulBgra32 => {
+            let (width, height) = (raster.width as usize, raster.height as usize);
+            let size = width.checked_mul(height)?.checked_mul(4)?;
+            if raster.data.len() < size {
+                return None;
+            }
+            RgbaImage::from_fn(width as u32, height as u32, |x, y| {
+                let index = (y as usize * width + x as usize) * 4;
+                let [blue, green, red, alpha] = [0, 1, 2, 3].map(|i| raster.data[index + i]);
+                let unmultiply = |channel: u8| match alpha {
+                    0 => 0,
+                    alpha => (channel as u32 * 255 / alpha as u32).min(255) as u8,
+                };
+                Rgba([unmultiply(red), unmultiply(green), unmultiply(blue), alpha])
+            })
+        }
+        format => {
+            trace!("[render_bitmap] unsupported glyph image format {format:?}");
+            return None;
+        }
+    };
+    if bitmap.width() == 0 || bitmap.height() == 0 {
+        return None;
+    }
+
+    let fit = (area.width / bitmap.width() as f32).min(area.height / bitmap.height() as f32);
+    let width = (bitmap.width() as f32 * fit).round().max(1.0) as u32;
+    let height = (bitmap.height() as f32 * fit).round().max(1.0) as u32;
+    let image = imageops::resize(&bitmap, width, height, imageops::FilterType::Triangle);
+    Some(ColorGlyph {
+        image,
+        left: (area.origin.0 + (area.width - width as f32) / 2.0).round() as i64,
+        top: (area.top + (area.height - height as f32) / 2.0).round() as i64,
+    })
+}
+
+/// Premultiplied RGBA color with channels in `0.0..=1.0`.
+type Color = [f32; 4];
+
+fn premultiply(color: RgbaColor) -> Color {
+    let alpha = color.alpha as f32 / 255.0;
+    [
+        color.red as f32 / 255.0 * alpha,
+        color.green as f32 / 255.0 * alpha,
+        color.blue as f32 / 255.0 * alpha,
+        alpha,
+    ]
+}
+
+enum Segment {
+    Line(Point, Point),
+    Quad(Point, Point, Point),
+    Cubic(Point, Point, Point, Point),
+}
+
+/// Collects a glyph outline in canvas pixels.
+struct PathBuilder {
+    transform: Transform,
+    segments: Vec<Segment>,
+    start: Point,
+    current: Point,
+}
+
+impl PathBuilder {
+    fn point(&self, x: f32, y: f32) -> Point {
+        let (x, y) = apply(&self.transform, x, y);
+        point(x, y)
+    }
+}
+
+impl OutlineBuilder for PathBuilder {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.start = self.point(x, y);
+        self.current = self.start;
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let to = self.point(x, y);
+        self.segments.push(Segment::Line(self.current, to));
+        self.current = to;
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let to = self.point(x, y);
+        let control = self.point(x1, y1);
+        self.segments.push(Segment::Quad(self.current, control, to));
+        self.current = to;
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let to = self.point(x, y);
+        let (control1, control2) = (self.point(x1, y1), self.point(x2, y2));
+        self.segments
+            .push(Segment::Cubic(self.current, control1, control2, to));
+        self.current = to;
+    }
+
+    fn close(&mut self) {
+        if self.current != self.start {
+            self.segments.push(Segment::Line(self.current, self.start));
+        }
+        self.current = self.start;
+    }
+}
+
+fn apply(transform: &Transform, x: f32, y: f32) -> (f32, f32) {
+    (
+        transform.a * x + transform.c * y + transform.e,
+        transform.b * x + transform.d * y + transform.f,
+    )
+}
+
+fn invert(transform: &Transform) -> Option<Transform> {
+    let Transform { a, b, c, d, e, f } = *transform;
+    let determinant = a * d - b * c;
+    if determinant.abs() < f32::EPSILON {
+        return None;
+    }
+    Some(Transform::new(
+        d / determinant,
+        -b / determinant,
+        -c / determinant,
+        a / determinant,
+        (c * f - d * e) / determinant,
+        (b * e - a * f) / determinant,
+    ))
+}
+
+/// Paints `COLR` glyphs onto a premultiplied RGBA canvas. Porter-Duff
+/// composite modes are supported, blend modes are drawn as source over.
+struct CanvasPainter<'f, 'a> {
+    face: &'f Face<'a>,
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    /// Glyph space to canvas pixels, the last entry is the current transform.
+    transforms: Vec<Transform>,
+    /// Outline stored by `outline_glyph`, in canvas pixels.
+    path: Vec<Segment>,
+    /// Coverage masks, the last entry is the intersection of every clip.
+    clips: Vec<Vec<f32>>,
+    layers: Vec<(Vec<Color>, CompositeMode)>,
+}
+
+impl<'f, 'a> CanvasPainter<'f, 'a> {
+    fn new(face: &'f Face<'a>, width: usize, height: usize, transform: Transform) -> Self {
+        Self {
+            face,
+            width,
+            height,
+            pixels: vec![[0.0; 4]; width * height],
+            transforms: vec![transform],
+            path: Vec::new(),
+            clips: Vec::new(),
+            layers: Vec::new(),
+        }
+    }
+
+    fn transform(&self) -> Transform {
+        *self.transforms.last().unwrap_or(&Transform::default())
+    }
+
+    /// Coverage of `segments`, intersected with the current clip.
+    fn coverage(&self, segments: &[Segment]) -> Vec<f32> {
+        let mut rasterizer = Rasterizer::new(self.width, self.height);
+        for segment in segments {
+            match *segment {
+                Segment::Line(p0, p1) => rasterizer.draw_line(p0, p1),
+                Segment::Quad(p0, p1, p2) => rasterizer.draw_quad(p0, p1, p2),
+                Segment::Cubic(p0, p1, p2, p3) => rasterizer.draw_cubic(p0, p1, p2, p3),
+            }
+        }
+        let mut mask = vec![0.0; self.width * self.height];
+        rasterizer.for_each_pixel(|index, coverage| mask[index] = coverage.min(1.0));
+        if let Some(clip) = self.clips.last() {
+            for (coverage, clip) in mask.iter_mut().zip(clip) {
+                *coverage *= clip;
+            }
+        }
+        mask
+    }
+
+    fn into_image(self) -> RgbaImage {
+        let (width, height) = (self.width as u32, self.height as u32);
+        RgbaImage::from_fn(width, height, |x, y| {
+            let [red, green, blue, alpha] = self.pixels[(y * width + x) as usize];
+            if alpha <= 0.0 {
+                return Rgba([0, 0, 0, 0]);
+            }
+            let channel = |value: f32| (value / alpha * 255.0).round().clamp(0.0, 255.0) as u8;
+            Rgba([
+                channel(red),
+                channel(green),
+                channel(blue),
+                (alpha * 255.0).round() as u8,
+            ])
+        })
+    }
+}
+
+impl<'a> Painter<'a> for CanvasPainter<'_, 'a> {
+    fn outline_glyph(&mut self, glyph_id: ttf_parser::GlyphId) {
+        let mut builder = PathBuilder {
+            transform: self.transform(),
+            segments: Vec::new(),
+            start: point(0.0, 0.0),
+            current: point(0.0, 0.0),
+        };
+        self.face.outline_glyph(glyph_id, &mut builder);
+        self.path = builder.segments;
+    }
+
+    fn paint(&mut self, paint: Paint<'a>) {
+        let mask = self.coverage(&self.path);
+        let inverse = invert(&self.transform());
+        let stops = match &paint {
+            Paint::Solid(_) => Vec::new(),
+            Paint::LinearGradient(gradient) => sorted_stops(gradient.stops(0, &[])),
+            Paint::RadialGradient(gradient) => sorted_stops(gradient.stops(0, &[])),
+            Paint::SweepGradient(gradient) => sorted_stops(gradient.stops(0, &[])),
+        };
+        let color_at = |x: f32, y: f32| -> Color {
+            let (x, y) = match &inverse {
+                Some(inverse) => apply(inverse, x, y),
+                None => (x, y),
+            };
+            match &paint {
+                Paint::Solid(color) => premultiply(*color),
+                Paint::LinearGradient(gradient) => {
+                    let t = linear_position(
+                        (gradient.x0, gradient.y0),
+                        (gradient.x1, gradient.y1),
+                        (gradient.x2, gradient.y2),
+                        (x, y),
+                    );
+                    gradient_color(&stops, t, gradient.extend)
+                }
+                Paint::RadialGradient(gradient) => {
+                    // Approximates the two point conical gradient with circles
+                    // centered on the end circle
+                    let distance = (x - gradient.x1).hypot(y - gradient.y1);
+                    let t = match gradient.r1 - gradient.r0 {
+                        span if span.abs() > f32::EPSILON => (distance - gradient.r0) / span,
+                        _ => 0.0,
+                    };
+                    gradient_color(&stops, t, gradient.extend)
+                }
+                Paint::SweepGradient(gradient) => {
+                    let angle = (y - gradient.center_y)
+                        .atan2(x - gradient.center_x)
+                        .to_degrees()
+                        .rem_euclid(360.0);
+                    let (start, end) = (gradient.start_angle * 180.0, gradient.end_angle * 180.0);
+                    let t = match end - start {
+                        span if span.abs() > f32::EPSILON => (angle - start) / span,
+                        _ => 0.0,
+                    };
+                    gradient_color(&stops, t, gradient.extend)
+                }
+            }
+        };
+
+        for (index, coverage) in mask.into_iter().enumerate() {
+            if coverage <= 0.0 {
+                continue;
+            }
+            let (x, y) = (index % self.width, index / self.width);
+            let source = color_at(x as f32 + 0.5, y as f32 + 0.5).map(|c| c * coverage);
+            let destination = &mut self.pixels[index];
+            *destination = composite(source, *destination, CompositeMode::SourceOver);
+        }
+    }
+
+    fn push_clip(&mut self) {
+        let mask = self.coverage(&self.path);
+        self.clips.push(mask);
+    }
+
+    fn push_clip_box(&mut self, clipbox: ClipBox) {
+        let transform = self.transform();
+        let corner = |x: f32, y: f32| {
+            let (x, y) = apply(&transform, x, y);
+            point(x, y)
+        };
+        let corners = [
+            corner(clipbox.x_min, clipbox.y_min),
+            corner(clipbox.x_max, clipbox.y_min),
+            corner(clipbox.x_max, clipbox.y_max),
+            corner(clipbox.x_min, clipbox.y_max),
+        ];
+        let segments = (0..4)
+            .map(|i| Segment::Line(corners[i], corners[(i + 1) % 4]))
+            .collect::<Vec<_>>();
+        let mask = self.coverage(&segments);
+        self.clips.push(mask);
+    }
+
+    fn pop_clip(&mut self) {
+        self.clips.pop();
+    }
+
+    fn push_layer(&mut self, mode: CompositeMode) {
+        let below = std::mem::replace(&mut self.pixels, vec![[0.0; 4]; self.width * self.height]);
+        self.layers.push((below, mode));
+    }
+
+    fn pop_layer(&mut self) {
+        let Some((below, mode)) = self.layers.pop() else {
+            return;
+        };
+        let layer = std::mem::replace(&mut self.pixels, below);
+        for (destination, source) in self.pixels.iter_mut().zip(layer) {
+            *destination = composite(source, *destination, mode);
+        }
+    }
+
+    fn push_transform(&mut self, transform: Transform) {
+        let combined = Transform::combine(self.transform(), transform);
+        self.transforms.push(combined);
+    }
+
+    fn pop_transform(&mut self) {
+        if self.transforms.len() > 1 {
+            self.transforms.pop();
+        }
+    }
+}
+
+/// Position of `p` along a `COLR` linear gradient, the gradient runs from
+/// `p0` towards `p1` projected onto the line perpendicular to `p0 p2`.
+fn linear_position(p0: (f32, f32), p1: (f32, f32), p2: (f32, f32), p: (f32, f32)) -> f32 {
+    let (dx, dy) = (p2.0 - p0.0, p2.1 - p0.1);
+    let (vx, vy) = (p1.0 - p0.0, p1.1 - p0.1);
+    let (nx, ny) = (-dy, dx);
+    let normal_length = nx * nx + ny * ny;
+    let (gx, gy) = if normal_length > f32::EPSILON {
+        let projection = (vx * nx + vy * ny) / normal_length;
+        (nx * projection, ny * projection)
+    } else {
+        (vx, vy)
+    };
+    let length = gx * gx + gy * gy;
+    if length <= f32::EPSILON {
+        return 0.0;
+    }
+    ((p.0 - p0.0) * gx + (p.1 - p0.1) * gy) / length
+}
+
+/// The stops of a gradient, in order of their offsets.
+fn sorted_stops(stops: impl Iterator<Item = ColorStop>) -> Vec<ColorStop> {
+    let mut stops = stops.collect::<Vec<_>>();
+    stops.sort_by(|a, b| a.stop_offset.total_cmp(&b.stop_offset));
+    stops
+}
+
+/// Color at `t` along a gradient of `stops` sorted by offset.
+fn gradient_color(stops: &[ColorStop], t: f32, extend: GradientExtend) -> Color {
+    let (Some(first), Some(last)) = (stops.first(), stops.last()) else {
+        return [0.0; 4];
+    };
+    let t = match extend {
+        GradientExtend::Pad => t,
+        GradientExtend::Repeat => t.rem_euclid(1.0),
+        GradientExtend::Reflect => {
+            let t = t.rem_euclid(2.0);
+            if t > 1.0 {
+                2.0 - t
+            } else {
+                t
+            }
+        }
+    };
+    if t <= first.stop_offset {
+        return premultiply(first.color);
+    }
+    if t >= last.stop_offset {
+        return premultiply(last.color);
+    }
+    for pair in stops.windows(2) {
+        let (from, to) = (&pair[0], &pair[1]);
+        if t <= to.stop_offset {
+            let span = to.stop_offset - from.stop_offset;
+            let weight = if span > f32::EPSILON {
+                (t - from.stop_offset) / span
+            } else {
+                1.0
+            };
+            let (from, to) = (premultiply(from.color), premultiply(to.color));
+            return [0, 1, 2, 3].map(|i| from[i] + (to[i] - from[i]) * weight);
+        }
+    }
+    premultiply(last.color)
+}
+
+/// Composites premultiplied `source` onto `destination`.
+fn composite(source: Color, destination: Color, mode: CompositeMode) -> Color {
+    let (source_alpha, destination_alpha) = (source[3], destination[3]);
+    let (source_factor, destination_factor) = match mode {
+        CompositeMode::Clear => (0.0, 0.0),
+        CompositeMode::Source => (1.0, 0.0),
+        CompositeMode::Destination => (0.0, 1.0),
+        CompositeMode::DestinationOver => (1.0 - destination_alpha, 1.0),
+        CompositeMode::SourceIn => (destination_alpha, 0.0),
+        CompositeMode::DestinationIn => (0.0, source_alpha),
+        CompositeMode::SourceOut => (1.0 - destination_alpha, 0.0),
+        CompositeMode::DestinationOut => (0.0, 1.0 - source_alpha),
+        CompositeMode::SourceAtop => (destination_alpha, 1.0 - source_alpha),
+        CompositeMode::DestinationAtop => (1.0 - destination_alpha, source_alpha),
+        CompositeMode::Xor => (1.0 - destination_alpha, 1.0 - source_alpha),
+        CompositeMode::Plus => (1.0, 1.0),
+        _ => (1.0, 1.0 - source_alpha),
+    };
+    [0, 1, 2, 3]
+        .map(|i| (source[i] * source_factor + destination[i] * destination_factor).clamp(0.0, 1.0))
+}
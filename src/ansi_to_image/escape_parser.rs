@@ -4,7 +4,10 @@ use std::slice::Iter;
 
 use crate::warn;
 
-use crate::ansi_to_image::color::{Color, ColorType};
+use crate::ansi_to_image::{
+    color::{Color, ColorType},
+    grid::UnderlineStyle,
+};
 
 #[derive(Debug)]
 pub(super) enum EscapeSequence {
@@ -16,9 +19,8 @@ pub(super) enum EscapeSequence {
     Italic,
     RapidBlink,
     SlowBlink,
-    Underline,
+    Underline(UnderlineStyle),
 
-    NotBold,
     NotUnderline,
     NormalIntensity,
     NotItalicNorBlackLetter,
@@ -28,6 +30,10 @@ pub(super) enum EscapeSequence {
     Conceal,
     CrossedOut,
 
+    NotReversed,
+    Reveal,
+    NotCrossedOut,
+
     DefaultForegroundColor,
     DefaultBackgroundColor,
 
@@ -36,18 +42,28 @@ pub(super) enum EscapeSequence {
 
     ForegroundColor(ColorType),
     BackgroundColor(ColorType),
+    UnderlineColor(ColorType),
+    DefaultUnderlineColor,
 
     DisableProportionalSpacing,
+    Overline,
+    NotOverline,
+    Superscript,
+    Subscript,
     NeitherSuperscriptNorSubscript,
 
-    NotReserved,
-
     Unimplemented(Vec<u16>),
     Ignore,
 }
 
 impl EscapeSequence {
-    pub(super) fn parse_params(params: Vec<&u16>) -> Vec<EscapeSequence> {
+    /// Parses the parameters of an SGR sequence, each item of `params` is a
+    /// parameter followed by its colon separated subparameters.
+    pub(super) fn parse_params(params: Vec<&[u16]>) -> Vec<EscapeSequence> {
+        // `CSI m` is the same as `CSI 0 m`
+        if params.is_empty() {
+            return vec![Self::Reset];
+        }
         let iter = &mut params.iter();
         let mut result = vec![];
         while iter.len() > 0 {
@@ -55,14 +71,23 @@ impl EscapeSequence {
         }
         result
     }
-    fn consume_and_parse(iter: &mut Iter<&u16>) -> Self {
+    fn consume_and_parse(iter: &mut Iter<&[u16]>) -> Self {
         if let Some(current) = iter.next() {
-            return match *current {
+            let (code, subparams) = current.split_first().unwrap_or((&0, &[]));
+            return match code {
                 0 => Self::Reset,
                 1 => Self::Bold,
                 2 => Self::Faint,
                 3 => Self::Italic,
-                4 => Self::Underline,
+                4 => match subparams.first() {
+                    None | Some(1) => Self::Underline(UnderlineStyle::Single),
+                    Some(0) => Self::NotUnderline,
+                    Some(2) => Self::Underline(UnderlineStyle::Double),
+                    Some(3) => Self::Underline(UnderlineStyle::Curly),
+                    Some(4) => Self::Underline(UnderlineStyle::Dotted),
+                    Some(5) => Self::Underline(UnderlineStyle::Dashed),
+                    Some(v) => Self::Unimplemented(vec![4, *v]),
+                },
                 5 => Self::SlowBlink,
                 6 => Self::RapidBlink,
 
@@ -83,7 +108,7 @@ impl EscapeSequence {
                 19 => Self::SetAlternativeFont,
 
                 20 => Self::BlackLetterFont,
-                21 => Self::NotBold,
+                21 => Self::Underline(UnderlineStyle::Double),
                 22 => Self::NormalIntensity,
                 23 => Self::NotItalicNorBlackLetter,
                 24 => Self::NotUnderline,
@@ -91,9 +116,9 @@ impl EscapeSequence {
 
                 26 => Self::Ignore, // Proportional spacing
 
-                27 => Self::NotReserved,
-                28 => Self::Ignore, // Reveal
-                29 => Self::Ignore, // Not crossed out
+                27 => Self::NotReversed,
+                28 => Self::Reveal,
+                29 => Self::NotCrossedOut,
 
                 30 => Self::ForegroundColor(ColorType::Normal(Color::Black)),
                 31 => Self::ForegroundColor(ColorType::Normal(Color::Red)),
@@ -103,8 +128,8 @@ impl EscapeSequence {
                 35 => Self::ForegroundColor(ColorType::Normal(Color::Magenta)),
                 36 => Self::ForegroundColor(ColorType::Normal(Color::Cyan)),
                 37 => Self::ForegroundColor(ColorType::Normal(Color::White)),
-                38 => match iter.next() {
-                    Some(mode) => Self::ForegroundColor(parse_color(mode, iter)),
+                38 => match parse_extended_color(subparams, iter) {
+                    Some(color) => Self::ForegroundColor(color),
                     None => {
                         warn!(
                             "[SEQUENCE_PARSER] foreground color mode is not supplied, parse_color(null, ...)",
@@ -122,8 +147,8 @@ impl EscapeSequence {
                 45 => Self::BackgroundColor(ColorType::Normal(Color::Magenta)),
                 46 => Self::BackgroundColor(ColorType::Normal(Color::Cyan)),
                 47 => Self::BackgroundColor(ColorType::Normal(Color::White)),
-                48 => match iter.next() {
-                    Some(mode) => Self::BackgroundColor(parse_color(mode, iter)),
+                48 => match parse_extended_color(subparams, iter) {
+                    Some(color) => Self::BackgroundColor(color),
                     None => {
                         warn!(
                             "[SEQUENCE_PARSER] background color mode is not supplied, parse_color(null, ...)",
@@ -133,8 +158,22 @@ impl EscapeSequence {
                 },
                 49 => Self::DefaultBackgroundColor,
                 50 => Self::DisableProportionalSpacing,
-                53 => Self::CrossedOut,
+                53 => Self::Overline,
+                55 => Self::NotOverline,
+
+                58 => match parse_extended_color(subparams, iter) {
+                    Some(color) => Self::UnderlineColor(color),
+                    None => {
+                        warn!(
+                            "[SEQUENCE_PARSER] underline color mode is not supplied, parse_color(null, ...)",
+                        );
+                        Self::Ignore
+                    }
+                },
+                59 => Self::DefaultUnderlineColor,
 
+                73 => Self::Superscript,
+                74 => Self::Subscript,
                 75 => Self::NeitherSuperscriptNorSubscript,
 
                 90 => Self::ForegroundColor(ColorType::Bright(Color::Black)),
@@ -162,10 +201,32 @@ impl EscapeSequence {
     }
 }
 
-fn parse_color(mode: &u16, iter: &mut Iter<&u16>) -> ColorType {
+/// Takes the next semicolon separated parameter, ignoring its subparameters.
+fn next_value(iter: &mut Iter<&[u16]>) -> Option<u16> {
+    iter.next().and_then(|param| param.first().copied())
+}
+
+/// Parses the color of SGR 38, 48 and 58, either from its colon separated
+/// subparameters (`38:2:<colorspace>:r:g:b`, `38:5:n`) or from the following
+/// semicolon separated parameters (`38;2;r;g;b`, `38;5;n`). Returns `None` when
+/// the color mode is missing.
+fn parse_extended_color(subparams: &[u16], iter: &mut Iter<&[u16]>) -> Option<ColorType> {
+    match subparams {
+        [] => {
+            let mode = next_value(iter)?;
+            let values = &mut iter.by_ref().filter_map(|param| param.first().copied());
+            Some(parse_color(mode, values))
+        }
+        // The colorspace id is optional and may be left empty (`38:2::r:g:b`)
+        [2, _colorspace, r, g, b, ..] => Some(parse_color(2, &mut [*r, *g, *b].into_iter())),
+        [mode, values @ ..] => Some(parse_color(*mode, &mut values.iter().copied())),
+    }
+}
+
+fn parse_color(mode: u16, values: &mut impl Iterator<Item = u16>) -> ColorType {
     match mode {
         5 => {
-            let color = iter.next();
+            let color = values.next();
             if let Some(color) = color {
                 let color = match color {
                     0 => ColorType::Normal(Color::Black),
@@ -187,29 +248,26 @@ fn parse_color(mode: &u16, iter: &mut Iter<&u16>) -> ColorType {
                     15 => ColorType::Bright(Color::White),
 
                     // These are fixed colors and could be used like ansi 38;5;numberm or 48;5;numberm
-                    16..=255 => ColorType::Fixed(**color as u8),
+                    16..=255 => ColorType::Fixed(color as u8),
 
                     v => {
                         warn!("[COLOR_PARSER] fixed color value out of range, parse_fixed_color(code: {})",v);
                         return ColorType::PrimaryForeground;
                     }
                 };
-                return color;
+                color
             } else {
                 warn!(
                     "[COLOR_PARSER] fixed color value not supplied, parse_fixed_color(code: null)"
                 );
 
-                return ColorType::PrimaryForeground;
+                ColorType::PrimaryForeground
             }
         }
-        2 => match (iter.next(), iter.next(), iter.next()) {
-            (Some(r), Some(g), Some(b)) => {
-                let color = ColorType::Rgb {
-                    field1: (**r as u8, **g as u8, **b as u8),
-                };
-                return color;
-            }
+        2 => match (values.next(), values.next(), values.next()) {
+            (Some(r), Some(g), Some(b)) => ColorType::Rgb {
+                field1: (r as u8, g as u8, b as u8),
+            },
             (r, g, b) => {
                 warn!(
                     "[COLOR_PARSER] rgb color value not supplied (correctly), parse_rgb_color({}, {}, {})",
@@ -217,7 +275,7 @@ fn parse_color(mode: &u16, iter: &mut Iter<&u16>) -> ColorType {
                     g.map(|i| i.to_string() ).unwrap_or("null".to_string()),
                     b.map(|i| i.to_string() ).unwrap_or("null".to_string())
                 );
-                return ColorType::PrimaryForeground;
+                ColorType::PrimaryForeground
             }
         },
         v => {
@@ -225,7 +283,87 @@ fn parse_color(mode: &u16, iter: &mut Iter<&u16>) -> ColorType {
                 "[COLOR_PARSER] color mode is not supplied correctly, parse_color({}, ...)",
                 v
             );
-            return ColorType::PrimaryForeground;
+            ColorType::PrimaryForeground
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extended_color(params: &[&[u16]]) -> Option<ColorType> {
+        let (first, rest) = params.split_first().unwrap();
+        parse_extended_color(&first[1..], &mut rest.iter())
+    }
+
+    #[test]
+    fn rgb_with_semicolons() {
+        assert_eq!(
+            extended_color(&[&[38], &[2], &[10], &[20], &[30]]),
+            Some(ColorType::Rgb {
+                field1: (10, 20, 30)
+            })
+        );
+    }
+
+    #[test]
+    fn rgb_with_colons() {
+        let rgb = Some(ColorType::Rgb {
+            field1: (10, 20, 30),
+        });
+        // vte reads an empty subparameter as 0
+        assert_eq!(extended_color(&[&[38, 2, 0, 10, 20, 30]]), rgb);
+        assert_eq!(extended_color(&[&[38, 2, 1, 10, 20, 30]]), rgb);
+        assert_eq!(extended_color(&[&[38, 2, 10, 20, 30]]), rgb);
+    }
+
+    #[test]
+    fn indexed_colors() {
+        assert_eq!(
+            extended_color(&[&[38, 5, 1]]),
+            Some(ColorType::Normal(Color::Red))
+        );
+        assert_eq!(
+            extended_color(&[&[48], &[5], &[9]]),
+            Some(ColorType::Bright(Color::Red))
+        );
+        assert_eq!(
+            extended_color(&[&[58, 5, 196]]),
+            Some(ColorType::Fixed(196))
+        );
+    }
+
+    #[test]
+    fn missing_color_mode() {
+        assert_eq!(extended_color(&[&[38]]), None);
+    }
+
+    #[test]
+    fn parameters_after_a_color_are_kept() {
+        let params: Vec<&[u16]> = vec![&[38], &[5], &[196], &[1]];
+        let sequences = EscapeSequence::parse_params(params);
+        assert!(matches!(
+            sequences[..],
+            [
+                EscapeSequence::ForegroundColor(ColorType::Fixed(196)),
+                EscapeSequence::Bold
+            ]
+        ));
+    }
+
+    #[test]
+    fn underline_styles() {
+        let params: Vec<&[u16]> = vec![&[4, 3], &[4, 0], &[4], &[21]];
+        let sequences = EscapeSequence::parse_params(params);
+        assert!(matches!(
+            sequences[..],
+            [
+                EscapeSequence::Underline(UnderlineStyle::Curly),
+                EscapeSequence::NotUnderline,
+                EscapeSequence::Underline(UnderlineStyle::Single),
+                EscapeSequence::Underline(UnderlineStyle::Double)
+            ]
+        ));
+    }
+}
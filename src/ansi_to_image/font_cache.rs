@@ -0,0 +1,180 @@
+//! Font files loaded from disk. They are kept for the lifetime of the plugin
+//! so later calls using the same fonts do not read them again.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    io,
+    ops::Deref,
+    path::PathBuf,
+    sync::{Mutex, PoisonError},
+    time::SystemTime,
+};
+
+use ab_glyph::{FontRef, InvalidFont, VariableFont};
+use include_flate::lazy_static;
+use ttf_parser::{Face, Tag};
+
+use crate::debug;
+
+lazy_static! {
+    static ref CACHE: Mutex<Cache> = Mutex::new(Cache::default());
+}
+
+/// A face to load: a font file, the index of the face when the file is a
+/// collection, and the values of its variation axes.
+#[derive(Debug, Clone, Default)]
+pub(super) struct FaceSpec {
+    pub(super) path: PathBuf,
+    pub(super) index: u32,
+    /// Value of the `wght` axis.
+    pub(super) weight: Option<f32>,
+    /// Value of the `ital` axis, or the fraction of the `slnt` axis range
+    /// for fonts that only have a slant axis.
+    pub(super) italic: Option<f32>,
+}
+
+/// A face loaded with ab_glyph, and the same face parsed by ttf-parser for
+/// the tables ab_glyph does not read. Both use the same collection index and
+/// variations.
+#[derive(Debug, Clone)]
+pub struct FontFace<'a> {
+    font: FontRef<'a>,
+    face: Option<Face<'a>>,
+}
+
+impl<'a> FontFace<'a> {
+    /// Face `index` of `data`, with its variation axes set to `variations`.
+    pub(super) fn new(
+        data: &'a [u8],
+        index: u32,
+        variations: &[(Tag, f32)],
+    ) -> Result<Self, InvalidFont> {
+        let mut font = FontRef::try_from_slice_and_index(data, index)?;
+        let mut face = Face::parse(data, index).ok();
+        for (tag, value) in variations {
+            font.set_variation(&tag.to_bytes(), *value);
+            if let Some(face) = &mut face {
+                face.set_variation(*tag, *value);
+            }
+        }
+        Ok(Self { font, face })
+    }
+
+    /// The face parsed by ttf-parser.
+    pub(super) fn face(&self) -> Option<&Face<'a>> {
+        self.face.as_ref()
+    }
+
+    /// Whether the face has a variation axis tagged `tag`.
+    pub(super) fn has_axis(&self, tag: &[u8; 4]) -> bool {
+        self.font.variations().iter().any(|axis| axis.tag == *tag)
+    }
+}
+
+impl<'a> Deref for FontFace<'a> {
+    type Target = FontRef<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.font
+    }
+}
+
+/// Font files by canonical path and modification time, a font edited on
+/// disk is read again. Every face of a file shares its buffer.
+#[derive(Default)]
+struct Cache {
+    buffers: HashMap<CacheKey, &'static [u8]>,
+}
+
+#[derive(PartialEq, Eq, Hash)]
+struct CacheKey {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+#[derive(Debug)]
+pub(super) enum FontLoadError {
+    Io(io::Error),
+    Invalid(InvalidFont),
+    FaceIndex { index: u32, count: u32 },
+    MissingAxis(&'static str),
+}
+
+impl Display for FontLoadError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            FontLoadError::Io(err) => write!(f, "cannot read font file: {err}"),
+            FontLoadError::Invalid(_) => write!(f, "not a valid font file"),
+            FontLoadError::FaceIndex { index, count } => {
+                write!(
+                    f,
+                    "face index {index} is out of range, the file has {count} faces"
+                )
+            }
+            FontLoadError::MissingAxis(axis) => write!(f, "the font has no {axis} axis"),
+        }
+    }
+}
+
+/// Loads the face described by `spec`, reading the file only the first time
+/// it is used.
+pub(super) fn load_font(spec: &FaceSpec) -> Result<FontFace<'static>, FontLoadError> {
+    let path = spec.path.canonicalize().map_err(FontLoadError::Io)?;
+    let modified = std::fs::metadata(&path)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    let key = CacheKey { path, modified };
+    let mut cache = CACHE.lock().unwrap_or_else(PoisonError::into_inner);
+    let data = match cache.buffers.get(&key) {
+        Some(data) => *data,
+        None => {
+            debug!("loading font `{}`", key.path.display());
+            let data = std::fs::read(&key.path).map_err(FontLoadError::Io)?;
+            // Fonts are borrowed by every image rendered with them, the
+            // buffer lives as long as the plugin
+            let data: &'static [u8] = Box::leak(data.into_boxed_slice());
+            cache.buffers.insert(key, data);
+            data
+        }
+    };
+    drop(cache);
+    let variations = resolve_variations(data, spec)?;
+    FontFace::new(data, spec.index, &variations).map_err(FontLoadError::Invalid)
+}
+
+/// Values of the variation axes `spec` sets, clamped to the axes' ranges.
+fn resolve_variations(data: &[u8], spec: &FaceSpec) -> Result<Vec<(Tag, f32)>, FontLoadError> {
+    let count = ttf_parser::fonts_in_collection(data).unwrap_or(1);
+    if spec.index >= count {
+        return Err(FontLoadError::FaceIndex {
+            index: spec.index,
+            count,
+        });
+    }
+    let face = Face::parse(data, spec.index).map_err(|_| FontLoadError::Invalid(InvalidFont))?;
+    let axis = |tag: &[u8; 4]| {
+        face.variation_axes()
+            .into_iter()
+            .find(|axis| axis.tag == Tag::from_bytes(tag))
+    };
+
+    let mut variations = Vec::new();
+    if let Some(weight) = spec.weight {
+        let axis = axis(b"wght").ok_or(FontLoadError::MissingAxis("weight"))?;
+        variations.push((axis.tag, weight.clamp(axis.min_value, axis.max_value)));
+    }
+    if let Some(italic) = spec.italic {
+        match (axis(b"ital"), axis(b"slnt")) {
+            (Some(axis), _) => {
+                variations.push((axis.tag, italic.clamp(axis.min_value, axis.max_value)))
+            }
+            // Slant is in degrees, negative values lean to the right
+            (None, Some(axis)) => {
+                variations.push((axis.tag, axis.min_value * italic.clamp(0.0, 1.0)))
+            }
+            (None, None) => return Err(FontLoadError::MissingAxis("italic")),
+        }
+    }
+    Ok(variations)
+}
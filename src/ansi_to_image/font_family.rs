@@ -1,22 +1,38 @@
 use std::fmt;
 use std::fmt::{Display, Formatter};
 
-use ab_glyph::FontRef;
 use include_flate::flate;
+
+use crate::warn;
+
+use super::{
+    bitmap_font::BitmapFont,
+    font_cache::{load_font, FaceSpec, FontFace},
+    system_fonts,
+};
+
+/// Value of the weight axis used for bold faces of variable fonts.
+const BOLD_WEIGHT: f32 = 700.0;
 type FontBuilder = fn() -> FontFamily<'static>;
 #[derive(Debug)]
 pub struct FontFamily<'a> {
     pub name: String,
-    pub regular: FontRef<'a>,
-    pub bold: FontRef<'a>,
-    pub italic: FontRef<'a>,
-    pub bold_italic: FontRef<'a>,
+    pub regular: FontFace<'a>,
+    /// Styled faces, the missing ones are synthesized from the others.
+    pub bold: Option<FontFace<'a>>,
+    pub italic: Option<FontFace<'a>>,
+    pub bold_italic: Option<FontFace<'a>>,
+    /// Fonts searched, in order, for glyphs missing from the family.
+    pub fallbacks: Vec<FontFace<'a>>,
+    /// Bitmap font drawn in place of the faces for the characters it has.
+    pub bitmap: Option<BitmapFont>,
 }
 
 impl FontFamily<'static> {
     fn all_fonts() -> Vec<(String, FontBuilder)> {
-        let mut result: Vec<(String, FontBuilder)> = vec![];
-        result.push(("SourceCodePro".to_string(), Self::source_code_pro));
+        #[allow(unused_mut)]
+        let mut result: Vec<(String, FontBuilder)> =
+            vec![("SourceCodePro".to_string(), Self::source_code_pro)];
         #[cfg(feature = "font-ubuntu")]
         result.push(("Ubuntu".to_string(), Self::ubuntu));
         #[cfg(feature = "font-iosevka_term")]
@@ -29,35 +45,108 @@ impl FontFamily<'static> {
         Self::all_fonts().into_iter().map(|i| i.0).collect()
     }
 
+    /// The embedded family called `name`, ignoring case, or else the
+    /// installed family, or else the default family.
     pub fn from_name(name: String) -> Self {
         for value in Self::all_fonts() {
-            if name == value.0 {
-                return value.1();
+            if name.eq_ignore_ascii_case(&value.0) {
+                let mut family = value.1();
+                family.fallbacks = Self::builtin_fallbacks(&value.0);
+                return family;
+            }
+        }
+        match Self::from_system(&name) {
+            Some(family) => family,
+            None => {
+                warn!("no font family named `{name}`, using the default font");
+                Self::default()
             }
         }
-        return Self::default();
     }
+
+    /// Loads the faces of an installed family, the ones that cannot be read
+    /// are left out and synthesized.
+    fn from_system(name: &str) -> Option<Self> {
+        let faces = system_fonts::find_family(name)?;
+        let load = |spec: Option<FaceSpec>| {
+            let spec = spec?;
+            load_font(&spec)
+                .inspect_err(|err| warn!("cannot load `{}`: {err}", spec.path.display()))
+                .ok()
+        };
+        let family = FontFamily {
+            name: name.to_string(),
+            regular: load(Some(faces.regular.clone()))?,
+            bold: load(faces.bold),
+            italic: load(faces.italic),
+            bold_italic: load(faces.bold_italic),
+            fallbacks: Self::builtin_fallbacks(""),
+            bitmap: None,
+        };
+        Some(family.with_variable_styles(&faces.regular))
+    }
+
+    /// Fills the styled faces the family lacks from the variation axes of
+    /// its regular face, styles without an axis are left to be synthesized.
+    pub(super) fn with_variable_styles(mut self, regular: &FaceSpec) -> Self {
+        let has_weight = self.regular.has_axis(b"wght");
+        let has_italic = self.regular.has_axis(b"ital") || self.regular.has_axis(b"slnt");
+        let variant = |weight: Option<f32>, italic: Option<f32>| {
+            let spec = FaceSpec {
+                weight: weight.or(regular.weight),
+                italic: italic.or(regular.italic),
+                ..regular.clone()
+            };
+            load_font(&spec).ok()
+        };
+        if self.bold.is_none() && has_weight {
+            self.bold = variant(Some(BOLD_WEIGHT), None);
+        }
+        if self.italic.is_none() && has_italic {
+            self.italic = variant(None, Some(1.0));
+        }
+        if self.bold_italic.is_none() && has_weight && has_italic {
+            self.bold_italic = variant(Some(BOLD_WEIGHT), Some(1.0));
+        }
+        self
+    }
+
+    /// Regular faces of the other embedded families, they cover some glyphs
+    /// the selected family does not.
+    fn builtin_fallbacks(except: &str) -> Vec<FontFace<'static>> {
+        Self::all_fonts()
+            .into_iter()
+            .filter(|(name, _)| name != except)
+            .map(|(_, builder)| builder().regular)
+            .collect()
+    }
+    /// Builds a family from font files, only the regular face is required.
     pub fn try_from_bytes(
         name: Option<String>,
         regular: &'static [u8],
-        bold: &'static [u8],
-        italic: &'static [u8],
-        bold_italic: &'static [u8],
+        bold: Option<&'static [u8]>,
+        italic: Option<&'static [u8]>,
+        bold_italic: Option<&'static [u8]>,
     ) -> Option<FontFamily<'static>> {
-        let regular = FontRef::try_from_slice(regular);
-        let bold = FontRef::try_from_slice(bold);
-        let italic = FontRef::try_from_slice(italic);
-        let bold_italic = FontRef::try_from_slice(bold_italic);
-        match (regular, bold, italic, bold_italic) {
-            (Ok(regular), Ok(bold), Ok(italic), Ok(bold_italic)) => {
-                return Some(FontFamily {
-                    name: name.unwrap_or("Custom".to_string()),
-                    regular,
-                    bold,
-                    italic,
-                    bold_italic,
-                })
-            }
+        let face = |bytes: Option<&'static [u8]>| match bytes {
+            Some(bytes) => FontFace::new(bytes, 0, &[]).map(Some),
+            None => Ok(None),
+        };
+        match (
+            FontFace::new(regular, 0, &[]),
+            face(bold),
+            face(italic),
+            face(bold_italic),
+        ) {
+            (Ok(regular), Ok(bold), Ok(italic), Ok(bold_italic)) => Some(FontFamily {
+                name: name.unwrap_or("Custom".to_string()),
+                regular,
+                bold,
+                italic,
+                bold_italic,
+                fallbacks: Vec::new(),
+                bitmap: None,
+            }),
             _ => None,
         }
     }
@@ -73,9 +162,9 @@ impl FontFamily<'static> {
         FontFamily::try_from_bytes(
             Some("SourceCodePro".to_string()),
             &REGULAR,
-            &BOLD,
-            &ITALIC,
-            &BOLD_ITALIC,
+            Some(&BOLD),
+            Some(&ITALIC),
+            Some(&BOLD_ITALIC),
         )
         .unwrap()
     }
@@ -93,9 +182,9 @@ impl FontFamily<'static> {
         FontFamily::try_from_bytes(
             Some("Ubunto".to_string()),
             &REGULAR,
-            &BOLD,
-            &ITALIC,
-            &BOLD_ITALIC,
+            Some(&BOLD),
+            Some(&ITALIC),
+            Some(&BOLD_ITALIC),
         )
         .unwrap()
     }
@@ -113,9 +202,9 @@ impl FontFamily<'static> {
         FontFamily::try_from_bytes(
             Some("IosevkaTerm".to_string()),
             &REGULAR,
-            &BOLD,
-            &ITALIC,
-            &BOLD_ITALIC,
+            Some(&BOLD),
+            Some(&ITALIC),
+            Some(&BOLD_ITALIC),
         )
         .unwrap()
     }
@@ -133,9 +222,9 @@ impl FontFamily<'static> {
         FontFamily::try_from_bytes(
             Some("AnonymousPro".to_string()),
             &REGULAR,
-            &BOLD,
-            &ITALIC,
-            &BOLD_ITALIC,
+            Some(&BOLD),
+            Some(&ITALIC),
+            Some(&BOLD_ITALIC),
         )
         .unwrap()
     }
@@ -143,7 +232,9 @@ impl FontFamily<'static> {
 
 impl Default for FontFamily<'static> {
     fn default() -> Self {
-        Self::source_code_pro()
+        let mut family = Self::source_code_pro();
+        family.fallbacks = Self::builtin_fallbacks("SourceCodePro");
+        family
     }
 }
 
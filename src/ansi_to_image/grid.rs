@@ -0,0 +1,264 @@
+use std::collections::VecDeque;
+
+use crate::ansi_to_image::color::ColorType;
+
+use super::printer::FontState;
+
+/// Graphic attributes shared by the printer's pen and every cell it writes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) struct Attributes {
+    pub(super) foreground: ColorType,
+    pub(super) background: ColorType,
+    pub(super) font: FontState,
+    pub(super) underline: UnderlineStyle,
+    /// Color of the underline, `None` draws it with the foreground color.
+    pub(super) underline_color: Option<ColorType>,
+    pub(super) reverse: bool,
+    pub(super) faint: bool,
+    pub(super) conceal: bool,
+    pub(super) crossed_out: bool,
+    pub(super) overline: bool,
+    pub(super) vertical_position: VerticalPosition,
+}
+
+impl Default for Attributes {
+    fn default() -> Self {
+        Self {
+            foreground: ColorType::PrimaryForeground,
+            background: ColorType::PrimaryBackground,
+            font: FontState::Normal,
+            underline: UnderlineStyle::None,
+            underline_color: None,
+            reverse: false,
+            faint: false,
+            conceal: false,
+            crossed_out: false,
+            overline: false,
+            vertical_position: VerticalPosition::Normal,
+        }
+    }
+}
+
+/// Underline styles selected by `SGR 4:n` (and `SGR 21` for double).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum UnderlineStyle {
+    None,
+    Single,
+    Double,
+    Curly,
+    Dotted,
+    Dashed,
+}
+
+/// Set by `SGR 73` and `SGR 74`, cleared by `SGR 75`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum VerticalPosition {
+    Normal,
+    Superscript,
+    Subscript,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Cell {
+    pub(super) character: char,
+    /// Zero width characters drawn on top of `character` (combining marks,
+    /// variation selectors, ZWJ joined emoji).
+    pub(super) combining: Vec<char>,
+    /// Columns taken by the character, wide characters are followed by a
+    /// spacer cell of width 0.
+    pub(super) width: usize,
+    pub(super) attributes: Attributes,
+}
+
+impl Cell {
+    /// An empty cell that keeps the background of `attributes`, as erased
+    /// cells do in terminals with background color erase.
+    pub(super) fn blank(attributes: Attributes) -> Self {
+        Self {
+            character: ' ',
+            combining: Vec::new(),
+            width: 1,
+            attributes: Attributes {
+                background: attributes.background,
+                ..Attributes::default()
+            },
+        }
+    }
+
+    /// The right half of a wide character, it keeps the attributes so the
+    /// background and decorations span both columns.
+    pub(super) fn spacer(attributes: Attributes) -> Self {
+        Self {
+            character: ' ',
+            combining: Vec::new(),
+            width: 0,
+            attributes,
+        }
+    }
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self::blank(Attributes::default())
+    }
+}
+
+/// Line size set by `DECSWL` (`ESC # 5`), `DECDWL` (`ESC # 6`) and `DECDHL`
+/// (`ESC # 3` / `ESC # 4`). Double height lines are sent twice, the first
+/// row shows the top half of the glyphs and the second one the bottom half.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(super) enum LineSize {
+    #[default]
+    Normal,
+    DoubleWidth,
+    DoubleHeightTop,
+    DoubleHeightBottom,
+}
+
+impl LineSize {
+    /// Number of grid columns each cell of the row covers.
+    pub(super) fn width_factor(self) -> usize {
+        match self {
+            Self::Normal => 1,
+            Self::DoubleWidth | Self::DoubleHeightTop | Self::DoubleHeightBottom => 2,
+        }
+    }
+
+    /// Vertical glyph scale of the row.
+    pub(super) fn height_factor(self) -> f32 {
+        match self {
+            Self::Normal | Self::DoubleWidth => 1.0,
+            Self::DoubleHeightTop | Self::DoubleHeightBottom => 2.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(super) struct Row {
+    pub(super) cells: Vec<Cell>,
+    pub(super) size: LineSize,
+}
+
+impl Row {
+    /// Blanks `start..end`, dropping the tail of the row instead when it would
+    /// only hold default cells.
+    fn erase(&mut self, start: usize, end: usize, blank: &Cell) {
+        if start >= end {
+            return;
+        }
+        if end >= self.cells.len() && *blank == Cell::default() {
+            self.cells.truncate(start);
+            return;
+        }
+        if self.cells.len() < end {
+            self.cells.resize(end, Cell::default());
+        }
+        for cell in &mut self.cells[start..end] {
+            *cell = blank.clone();
+        }
+    }
+}
+
+/// Columns and rows the cursor can reach when the output has no fixed size,
+/// so stray cursor movement cannot grow the grid without bound. Output
+/// longer than `MAX_ROWS` scrolls, keeping its last rows.
+pub(super) const MAX_COLUMNS: usize = 2000;
+pub(super) const MAX_ROWS: usize = 10_000;
+
+/// Dense rows × columns cell buffer. Rows and columns grow on demand, as the
+/// captured output has no fixed screen size.
+#[derive(Debug, Default)]
+pub(super) struct Grid {
+    pub(super) rows: VecDeque<Row>,
+}
+
+impl Grid {
+    pub(super) fn row_mut(&mut self, row: usize) -> &mut Row {
+        if self.rows.len() <= row {
+            self.rows.resize_with(row + 1, Row::default);
+        }
+        &mut self.rows[row]
+    }
+
+    /// Size of `row`, rows that were never written to are normal sized.
+    pub(super) fn line_size(&self, row: usize) -> LineSize {
+        self.rows.get(row).map_or(LineSize::Normal, |row| row.size)
+    }
+
+    pub(super) fn cell_mut(&mut self, column: usize, row: usize) -> &mut Cell {
+        let cells = &mut self.row_mut(row).cells;
+        if cells.len() <= column {
+            cells.resize(column + 1, Cell::default());
+        }
+        &mut cells[column]
+    }
+
+    /// Blanks the other half of a wide character when the cell at `column` is
+    /// about to be overwritten.
+    pub(super) fn split_wide(&mut self, column: usize, row: usize) {
+        let Some(line) = self.rows.get_mut(row) else {
+            return;
+        };
+        match line.cells.get(column).map(|cell| cell.width) {
+            Some(0) if column > 0 => {
+                let leading = &mut line.cells[column - 1];
+                *leading = Cell::blank(leading.attributes);
+            }
+            Some(2) => {
+                if let Some(spacer) = line.cells.get_mut(column + 1) {
+                    *spacer = Cell::blank(spacer.attributes);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Inserts an empty row before `row`, shifting the following rows down.
+    /// Rows pushed past `MAX_ROWS` are dropped.
+    pub(super) fn insert_row(&mut self, row: usize) {
+        if row <= self.rows.len() {
+            self.rows.insert(row, Row::default());
+            self.rows.truncate(MAX_ROWS);
+        }
+    }
+
+    /// Drops the first row, shifting the others up.
+    pub(super) fn scroll_up(&mut self) {
+        self.rows.pop_front();
+    }
+
+    /// Width of the widest row, in columns. Cells of double width rows count
+    /// as two columns.
+    pub(super) fn columns(&self) -> usize {
+        self.rows
+            .iter()
+            .map(|row| row.cells.len() * row.size.width_factor())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Blanks the columns `start..end` of `row`.
+    pub(super) fn erase_columns(&mut self, row: usize, start: usize, end: usize, blank: &Cell) {
+        if row < self.rows.len() || *blank != Cell::default() {
+            self.row_mut(row).erase(start, end, blank);
+        }
+    }
+
+    /// Blanks every row in `start..end`, erased rows become single width.
+    /// Rows are blanked up to `columns`, or up to their last cell when the
+    /// output has no fixed width.
+    pub(super) fn erase_rows(
+        &mut self,
+        start: usize,
+        end: usize,
+        columns: Option<usize>,
+        blank: &Cell,
+    ) {
+        let end = end.min(self.rows.len());
+        for row in start..end {
+            let row = &mut self.rows[row];
+            row.size = LineSize::Normal;
+            row.erase(0, columns.unwrap_or(row.cells.len()), blank);
+        }
+    }
+}
@@ -0,0 +1,254 @@
+//! HTML output, a `<pre>` of flat spans. Palette colors are set by a CSS
+//! class per palette slot, so the theme can be changed by swapping the
+//! stylesheet. The classes set the `--fg` and `--bg` custom properties,
+//! which the spans are painted with, so rules like the one of faint text can
+//! be derived from them.
+
+use std::fmt::Write;
+
+use super::{
+    color::{Color, ColorType},
+    grid::{Cell, LineSize, UnderlineStyle, VerticalPosition},
+    markup::escape_cells,
+    palette::Palette,
+    printer::{FontState, Printer},
+};
+
+/// Class of the `<pre>`, the stylesheet only applies inside of it.
+const ROOT_CLASS: &str = "nu-image";
+
+/// Palette slots with a class, `fg-<slot>` sets the text color and
+/// `bg-<slot>` the background. The 256 color slots above 15 are classed by
+/// their number.
+const SLOTS: [(&str, ColorType); 18] = [
+    ("foreground", ColorType::PrimaryForeground),
+    ("background", ColorType::PrimaryBackground),
+    ("black", ColorType::Normal(Color::Black)),
+    ("red", ColorType::Normal(Color::Red)),
+    ("green", ColorType::Normal(Color::Green)),
+    ("yellow", ColorType::Normal(Color::Yellow)),
+    ("blue", ColorType::Normal(Color::Blue)),
+    ("magenta", ColorType::Normal(Color::Magenta)),
+    ("cyan", ColorType::Normal(Color::Cyan)),
+    ("white", ColorType::Normal(Color::White)),
+    ("bright-black", ColorType::Bright(Color::Black)),
+    ("bright-red", ColorType::Bright(Color::Red)),
+    ("bright-green", ColorType::Bright(Color::Green)),
+    ("bright-yellow", ColorType::Bright(Color::Yellow)),
+    ("bright-blue", ColorType::Bright(Color::Blue)),
+    ("bright-magenta", ColorType::Bright(Color::Magenta)),
+    ("bright-cyan", ColorType::Bright(Color::Cyan)),
+    ("bright-white", ColorType::Bright(Color::White)),
+];
+
+/// Classes of the other attributes, the rules do not depend on the palette.
+/// Each rule is on a line of its own, with a single selector, so it can be
+/// scoped to the `<pre>`.
+const ATTRIBUTE_RULES: &str = "\
+.faint { color: color-mix(in srgb, var(--fg) 50%, var(--bg)); }
+.bold { font-weight: bold; }
+.italic { font-style: italic; }
+.concealed { color: transparent; }
+.superscript { vertical-align: super; font-size: smaller; }
+.subscript { vertical-align: sub; font-size: smaller; }
+.double-width { display: inline-block; transform-origin: 0 0; transform: scale(2, 1); }
+.double-height { display: inline-block; transform-origin: 0 0; transform: scale(2, 2); }";
+
+/// Renders the printer's grid as an HTML document.
+pub(super) fn render_html(printer: &Printer) -> String {
+    let palette = &printer.settings().palette;
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<style>\n");
+    html.push_str(&stylesheet(palette));
+    html.push_str("</style>\n</head>\n<body>\n");
+    let _ = write!(html, r#"<pre class="{ROOT_CLASS}">"#);
+
+    for row in &printer.grid().rows {
+        // The top half of a double height line is drawn at full size over
+        // the bottom half, which is left empty
+        let wrapper = match row.size {
+            LineSize::Normal => None,
+            LineSize::DoubleWidth => Some("double-width"),
+            LineSize::DoubleHeightTop => Some("double-height"),
+            LineSize::DoubleHeightBottom => {
+                html.push('\n');
+                continue;
+            }
+        };
+        if let Some(class) = wrapper {
+            let _ = write!(html, r#"<span class="{class}">"#);
+        }
+        write_row(&mut html, printer, &row.cells);
+        if wrapper.is_some() {
+            html.push_str("</span>");
+        }
+        html.push('\n');
+    }
+
+    html.push_str("</pre>\n</body>\n</html>\n");
+    html
+}
+
+/// Writes a span for each run of cells with the same style, unstyled text
+/// is written as is. Unstyled blanks at the end of the row are left out.
+fn write_row(html: &mut String, printer: &Printer, cells: &[Cell]) {
+    let styles: Vec<Style> = cells.iter().map(|cell| Style::of(cell, printer)).collect();
+    let end = (0..cells.len())
+        .rfind(|column| cells[*column].character != ' ' || styles[*column] != Style::default())
+        .map_or(0, |column| column + 1);
+
+    let mut column = 0;
+    while column < end {
+        let style = &styles[column];
+        let run_end = (column..end)
+            .find(|next| styles[*next] != *style)
+            .unwrap_or(end);
+        let text = escape_cells(&cells[column..run_end]);
+        if *style == Style::default() {
+            html.push_str(&text);
+        } else {
+            html.push_str("<span");
+            if !style.classes.is_empty() {
+                let _ = write!(html, r#" class="{}""#, style.classes.join(" "));
+            }
+            if !style.inline.is_empty() {
+                let _ = write!(html, r#" style="{}""#, style.inline.join("; "));
+            }
+            let _ = write!(html, ">{text}</span>");
+        }
+        column = run_end;
+    }
+}
+
+/// Classes and inline declarations of a cell. RGB colors, which have no
+/// slot, and decorations, which combine into a single property, are set
+/// inline.
+#[derive(Debug, Default, PartialEq)]
+struct Style {
+    classes: Vec<String>,
+    inline: Vec<String>,
+}
+
+impl Style {
+    fn of(cell: &Cell, printer: &Printer) -> Self {
+        let palette = &printer.settings().palette;
+        let attributes = &cell.attributes;
+        let mut style = Style::default();
+        let (foreground, background) = if attributes.reverse {
+            (attributes.background, attributes.foreground)
+        } else {
+            (attributes.foreground, attributes.background)
+        };
+        if foreground != ColorType::PrimaryForeground {
+            style.color("fg", foreground, palette);
+        }
+        if background != ColorType::PrimaryBackground {
+            style.color("bg", background, palette);
+        }
+        if attributes.faint {
+            // Blended into the background like in images, opacity would
+            // fade the background too
+            style.classes.push("faint".to_string());
+        }
+
+        if matches!(attributes.font, FontState::Bold | FontState::ItalicBold) {
+            style.classes.push("bold".to_string());
+        }
+        if matches!(attributes.font, FontState::Italic | FontState::ItalicBold) {
+            style.classes.push("italic".to_string());
+        }
+        if attributes.conceal {
+            style.classes.push("concealed".to_string());
+        }
+        match attributes.vertical_position {
+            VerticalPosition::Normal => {}
+            VerticalPosition::Superscript => style.classes.push("superscript".to_string()),
+            VerticalPosition::Subscript => style.classes.push("subscript".to_string()),
+        }
+        // Concealed text hides its decorations too, a set decoration color
+        // would show them through the transparent text color
+        if attributes.conceal {
+            return style;
+        }
+
+        let mut lines = Vec::new();
+        if attributes.underline != UnderlineStyle::None {
+            lines.push("underline");
+        }
+        if attributes.overline {
+            lines.push("overline");
+        }
+        if attributes.crossed_out {
+            lines.push("line-through");
+        }
+        if !lines.is_empty() {
+            style
+                .inline
+                .push(format!("text-decoration-line: {}", lines.join(" ")));
+        }
+        let decoration_style = match attributes.underline {
+            UnderlineStyle::Double => Some("double"),
+            UnderlineStyle::Curly => Some("wavy"),
+            UnderlineStyle::Dotted => Some("dotted"),
+            UnderlineStyle::Dashed => Some("dashed"),
+            UnderlineStyle::None | UnderlineStyle::Single => None,
+        };
+        if let Some(decoration_style) = decoration_style {
+            style
+                .inline
+                .push(format!("text-decoration-style: {decoration_style}"));
+        }
+        if let Some(color) = attributes.underline_color {
+            let color = css_color(palette.get_color(color));
+            style.inline.push(format!("text-decoration-color: {color}"));
+        }
+        style
+    }
+
+    /// Sets `color` with the class of its palette slot, or else inline as
+    /// the `--<prefix>` custom property.
+    fn color(&mut self, prefix: &str, color: ColorType, palette: &Palette) {
+        if let ColorType::Fixed(number) = color {
+            self.classes.push(format!("{prefix}-{number}"));
+        } else if let Some((name, _)) = SLOTS.iter().find(|(_, slot)| *slot == color) {
+            self.classes.push(format!("{prefix}-{name}"));
+        } else {
+            let color = css_color(palette.get_color(color));
+            self.inline.push(format!("--{prefix}: {color}"));
+        }
+    }
+}
+
+/// The rules of the palette slots and of the attributes, scoped to the
+/// `<pre>`.
+fn stylesheet(palette: &Palette) -> String {
+    let mut css = String::new();
+    let _ = writeln!(
+        css,
+        ".{ROOT_CLASS} {{ --fg: {}; --bg: {}; color: var(--fg); background-color: var(--bg); padding: 0.5em; }}",
+        css_color(palette.get_color(ColorType::PrimaryForeground)),
+        css_color(palette.get_color(ColorType::PrimaryBackground))
+    );
+    let _ = writeln!(
+        css,
+        ".{ROOT_CLASS} span {{ color: var(--fg); background-color: var(--bg); }}"
+    );
+    let fixed = (16..=u8::MAX).map(|number| (number.to_string(), ColorType::Fixed(number)));
+    let slots = SLOTS.iter().map(|(name, slot)| (name.to_string(), *slot));
+    for (name, slot) in slots.chain(fixed) {
+        let color = css_color(palette.get_color(slot));
+        let _ = writeln!(css, ".{ROOT_CLASS} .fg-{name} {{ --fg: {color}; }}");
+        let _ = writeln!(css, ".{ROOT_CLASS} .bg-{name} {{ --bg: {color}; }}");
+    }
+    for rule in ATTRIBUTE_RULES.lines() {
+        let _ = writeln!(css, ".{ROOT_CLASS} {rule}");
+    }
+    css
+}
+
+fn css_color([red, green, blue, alpha]: [u8; 4]) -> String {
+    match alpha {
+        u8::MAX => format!("#{red:02x}{green:02x}{blue:02x}"),
+        _ => format!("rgba({red}, {green}, {blue}, {:.3})", alpha as f32 / 255.0),
+    }
+}
@@ -1,5 +1,12 @@
 use ab_glyph::PxScale;
 
+/// Font size used when none is given, in pixels.
+pub(super) const DEFAULT_FONT_SIZE: f32 = 50.0;
+/// Largest font size accepted, in pixels.
+pub(super) const MAX_FONT_SIZE: f32 = 1000.0;
+/// Largest line height accepted, as a multiple of the font height.
+pub(super) const MAX_LINE_HEIGHT: f32 = 10.0;
+
 #[derive(Clone, Copy)]
 pub struct InternalScale {
     /// Horizontal scale, in pixels.
@@ -17,11 +24,8 @@ pub struct InternalScale {
 //     }
 // }
 
-impl Into<PxScale> for InternalScale {
-    fn into(self) -> PxScale {
-        PxScale {
-            x: self.x,
-            y: self.y,
-        }
+impl From<InternalScale> for PxScale {
+    fn from(val: InternalScale) -> Self {
+        PxScale { x: val.x, y: val.y }
     }
 }
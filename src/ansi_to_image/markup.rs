@@ -0,0 +1,27 @@
+//! Text escaping shared by the SVG and HTML writers.
+
+use super::grid::Cell;
+
+/// The text of `cells` escaped for XML and HTML. Spacers after wide
+/// characters have no text of their own.
+pub(super) fn escape_cells(cells: &[Cell]) -> String {
+    let mut text = String::new();
+    for cell in cells.iter().filter(|cell| cell.width != 0) {
+        for character in std::iter::once(cell.character).chain(cell.combining.iter().copied()) {
+            escape_into(&mut text, character);
+        }
+    }
+    text
+}
+
+/// Appends `character` escaped, dropping the control characters XML does
+/// not allow.
+fn escape_into(text: &mut String, character: char) {
+    match character {
+        '&' => text.push_str("&amp;"),
+        '<' => text.push_str("&lt;"),
+        '>' => text.push_str("&gt;"),
+        character if character.is_control() => {}
+        character => text.push(character),
+    }
+}
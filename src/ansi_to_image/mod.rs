@@ -1,12 +1,28 @@
+#[allow(clippy::module_inception)]
 mod ansi_to_image;
+mod bitmap_font;
+mod box_drawing;
+mod cell_width;
+mod charset;
 mod color;
+mod color_glyph;
 // mod escape;
 mod escape_parser;
+mod font_cache;
 mod font_family;
+mod grid;
+mod html;
 mod internal_scale;
+mod markup;
 mod nu_plugin;
+mod output_format;
 mod palette;
 mod printer;
+mod shaping;
+mod svg;
+mod synthetic_style;
+mod system_fonts;
+mod tab_stops;
 pub use font_family::FontFamily;
 pub use nu_plugin::ansi_to_image;
 pub use palette::Palette;
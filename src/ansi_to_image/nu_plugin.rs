@@ -1,7 +1,9 @@
-use std::{fs::File, io::Read, path::PathBuf, time::SystemTime};
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 
 use crate::{debug, error, warn};
-use ab_glyph::FontRef;
 use nu_plugin::EvaluatedCall;
 use nu_protocol::{LabeledError, Span, Value};
 
@@ -9,7 +11,13 @@ use crate::FontFamily;
 
 use super::{
     ansi_to_image::make_image,
+    bitmap_font::BitmapFont,
+    cell_width::AmbiguousWidth,
+    font_cache::{load_font, FaceSpec, FontFace},
+    internal_scale::{DEFAULT_FONT_SIZE, MAX_FONT_SIZE, MAX_LINE_HEIGHT},
+    output_format::OutputFormat,
     palette::{strhex_to_rgba, Palette},
+    tab_stops::DEFAULT_TAB_WIDTH,
 };
 
 pub fn ansi_to_image(
@@ -34,13 +42,51 @@ pub fn ansi_to_image(
         }
     };
     let size = match call.get_flag_value("width") {
-        Some(val) => match val.as_int().ok() {
-            Some(value) => Some(value as u32),
-            _ => None,
-        },
+        Some(val) => val.as_int().ok().map(|value| value as u32),
         _ => None,
     };
-    let font: FontFamily<'_> = resolve_font(call);
+    let font_size = number_flag(
+        call,
+        "font-size",
+        DEFAULT_FONT_SIZE,
+        true,
+        Some(MAX_FONT_SIZE),
+    )?;
+    let line_height = number_flag(call, "line-height", 1.0, true, Some(MAX_LINE_HEIGHT))?;
+    let letter_spacing = number_flag(call, "letter-spacing", 0.0, false, None)?;
+    let tab_width = match call.get_flag_value("tab-width") {
+        Some(val) => match val.as_int() {
+            Ok(value) if value > 0 => value as usize,
+            _ => {
+                return Err(make_params_err(
+                    "`tab-width` must be a positive integer".to_string(),
+                    val.span(),
+                ))
+            }
+        },
+        None => DEFAULT_TAB_WIDTH,
+    };
+    let ambiguous_width = match call.get_flag_value("ambiguous-width") {
+        Some(val) => match val.as_str().ok().and_then(AmbiguousWidth::from_name) {
+            Some(policy) => policy,
+            None => {
+                return Err(make_params_err(
+                    "`ambiguous-width` must be either `narrow` or `wide`".to_string(),
+                    val.span(),
+                ))
+            }
+        },
+        None => AmbiguousWidth::default(),
+    };
+    let ligatures = call
+        .has_flag("ligatures")
+        .map_err(|err| make_params_err(err.to_string(), call.head))?;
+    let embed_fonts = call
+        .has_flag("embed-fonts")
+        .map_err(|err| make_params_err(err.to_string(), call.head))?;
+    let mut font: FontFamily<'_> = resolve_font(engine, call)?;
+    resolve_fallback_fonts(engine, call, &mut font)?;
+    let format = resolve_format(call)?;
     let out_path = call.opt::<String>(0);
 
     let out = match out_path {
@@ -61,27 +107,31 @@ pub fn ansi_to_image(
         }
         _ => {
             let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH);
-            let current = engine.get_current_dir().map(|p| PathBuf::from(p));
+            let current = engine.get_current_dir().map(PathBuf::from);
             if let (Ok(now), Ok(current)) = (now, current) {
                 let current = &mut current.clone();
-                current.push(PathBuf::from(format!("nu-image-{}.png", now.as_secs())));
+                current.push(PathBuf::from(format!(
+                    "nu-image-{}.{}",
+                    now.as_secs(),
+                    format.extension()
+                )));
                 Some(current.to_owned())
             } else {
                 None
             }
         }
     };
-    if let None = out {
+    if out.is_none() {
         return Err(make_params_err(
-            format!("cannot use time stamp as the file name timestamp please provide output path explicitly"),
+            "cannot use time stamp as the file name timestamp please provide output path explicitly".to_string(),
             call.head,
         ));
     }
     let theme = match call
         .get_flag_value("theme")
-        .map(|i| i.as_str().map(|f| f.to_string()))
+        .and_then(|i| i.as_str().ok().map(|f| f.to_string()))
     {
-        Some(Ok(name)) => {
+        Some(name) => {
             if let Some(theme) = Palette::from_name(name.to_string()) {
                 theme
             } else {
@@ -93,8 +143,28 @@ pub fn ansi_to_image(
     };
     let theme = load_custom_theme(call, theme);
 
-    let path = PathBuf::from(out.unwrap());
-    make_image(path.as_path(), font, size, i, theme);
+    let path = out.unwrap();
+    make_image(
+        path.as_path(),
+        format,
+        font,
+        size,
+        font_size,
+        line_height,
+        letter_spacing,
+        tab_width,
+        ambiguous_width,
+        ligatures,
+        embed_fonts,
+        i,
+        theme,
+    )
+    .map_err(|err| {
+        make_params_err(
+            format!("cannot write `{}`: {err}", path.display()),
+            call.head,
+        )
+    })?;
 
     Ok(Value::string(
         path.to_str().unwrap_or("error reading path").to_owned(),
@@ -102,56 +172,227 @@ pub fn ansi_to_image(
     ))
 }
 
-fn resolve_font(call: &EvaluatedCall) -> FontFamily<'static> {
-    let mut font: FontFamily<'static> = match call.get_flag_value("font").map(|value| match value {
-        Value::String { val, .. } => Some(FontFamily::from_name(val)),
-        _ => None,
-    }) {
-        Some(value) => {
-            if let Some(font) = value {
-                font
-            } else {
-                FontFamily::default()
+/// The format of the `--format` flag, or else of the output path's extension,
+/// PNG when there is neither. `--quality` sets the quality of JPEG output.
+fn resolve_format(call: &EvaluatedCall) -> Result<OutputFormat, LabeledError> {
+    let unsupported = |name: &str, span: Span| {
+        make_params_err(
+            format!(
+                "unsupported output format `{name}`, expected one of {:?}",
+                OutputFormat::list()
+            ),
+            span,
+        )
+    };
+    let format = match (call.get_flag_value("format"), call.positional.first()) {
+        (Some(value), path) => {
+            let name = value
+                .as_str()
+                .map_err(|err| make_params_err(err.to_string(), value.span()))?;
+            let format =
+                OutputFormat::from_name(name).ok_or_else(|| unsupported(name, value.span()))?;
+            // The flag wins over an extension of another format, likely a mistake
+            let path = path.and_then(|path| path.as_str().ok()).map(Path::new);
+            if let Some(path) = path.filter(|path| path.extension().is_some()) {
+                let same = OutputFormat::from_path(path).is_some_and(|of_path| {
+                    std::mem::discriminant(&of_path) == std::mem::discriminant(&format)
+                });
+                if !same {
+                    warn!(
+                        "writing {name} output to `{}`, its extension is of another format",
+                        path.display()
+                    );
+                }
+            }
+            format
+        }
+        (None, Some(value)) => {
+            let path = value
+                .as_str()
+                .map(PathBuf::from)
+                .map_err(|err| make_params_err(err.to_string(), value.span()))?;
+            match path.extension() {
+                Some(extension) => OutputFormat::from_path(&path)
+                    .ok_or_else(|| unsupported(&extension.to_string_lossy(), value.span()))?,
+                None => OutputFormat::Png,
             }
         }
-        None => FontFamily::default(),
+        (None, None) => OutputFormat::Png,
     };
-    // TODO custom fonts disabled for now
-    if let Some(path) = call.get_flag_value("font-regular") {
-        let buffer = load_file(path);
-        font.regular = FontRef::try_from_slice(buffer).unwrap();
+    match (call.get_flag_value("quality"), format) {
+        (None, format) => Ok(format),
+        (Some(value), OutputFormat::Jpeg { .. }) => match value.as_int() {
+            Ok(quality @ 1..=100) => Ok(OutputFormat::Jpeg {
+                quality: quality as u8,
+            }),
+            _ => Err(make_params_err(
+                "`quality` must be an integer from 1 to 100".to_string(),
+                value.span(),
+            )),
+        },
+        (Some(value), _) => Err(make_params_err(
+            "`quality` only applies to JPEG output".to_string(),
+            value.span(),
+        )),
     }
-    if let Some(path) = call.get_flag_value("font-bold") {
-        let buffer = load_file(path);
-        font.bold = FontRef::try_from_slice(buffer).unwrap();
+}
+
+/// Reads the numeric flag `name`, `default` when it is not given. Values
+/// above `max` are rejected.
+fn number_flag(
+    call: &EvaluatedCall,
+    name: &str,
+    default: f32,
+    positive: bool,
+    max: Option<f32>,
+) -> Result<f32, LabeledError> {
+    match call.get_flag_value(name) {
+        Some(val) => match val.coerce_float().map(|value| value as f32) {
+            Ok(value)
+                if value.is_finite()
+                    && (!positive || value > 0.0)
+                    && max.is_none_or(|max| value <= max) =>
+            {
+                Ok(value)
+            }
+            _ => {
+                let kind = if positive {
+                    "a positive number"
+                } else {
+                    "a number"
+                };
+                let text = match max {
+                    Some(max) => format!("`{name}` must be {kind} up to {max}"),
+                    None => format!("`{name}` must be {kind}"),
+                };
+                Err(make_params_err(text, val.span()))
+            }
+        },
+        None => Ok(default),
     }
-    if let Some(path) = call.get_flag_value("font-italic") {
-        let buffer = load_file(path);
-        font.italic = FontRef::try_from_slice(buffer).unwrap();
+}
+
+fn resolve_font(
+    engine: &nu_plugin::EngineInterface,
+    call: &EvaluatedCall,
+) -> Result<FontFamily<'static>, LabeledError> {
+    let mut font: FontFamily<'static> = match call.get_flag_value("font") {
+        Some(Value::String { val, .. }) => FontFamily::from_name(val),
+        _ => FontFamily::default(),
+    };
+    let mut regular = None;
+    if let Some(value) = call.get_flag_value("custom-font-regular") {
+        font.regular = read_font(engine, &value)?;
+        // Styles not given alongside a custom regular face come from its
+        // variation axes or are synthesized from it
+        font.bold = None;
+        font.italic = None;
+        font.bold_italic = None;
+        regular = Some(face_spec(engine, &value)?);
+    }
+    if let Some(path) = call.get_flag_value("custom-font-bold") {
+        font.bold = Some(read_font(engine, &path)?);
+    }
+    if let Some(path) = call.get_flag_value("custom-font-italic") {
+        font.italic = Some(read_font(engine, &path)?);
     }
-    if let Some(path) = call.get_flag_value("bold-italic") {
-        let buffer = load_file(path);
-        font.bold_italic = FontRef::try_from_slice(buffer).unwrap();
+    if let Some(path) = call.get_flag_value("custom-font-bold_italic") {
+        font.bold_italic = Some(read_font(engine, &path)?);
     }
-    font
+    if let Some(value) = call.get_flag_value("bitmap-font") {
+        let path = value
+            .as_str()
+            .map_err(|err| make_params_err(err.to_string(), value.span()))?;
+        let bitmap = BitmapFont::load(&flag_path(engine, path))
+            .map_err(|err| make_params_err(err.to_string(), value.span()))?;
+        font.bitmap = Some(bitmap);
+    }
+    Ok(match regular {
+        Some(regular) => font.with_variable_styles(&regular),
+        None => font,
+    })
 }
 
-// fn load_file<'a>(path: Value) -> &'a [u8] {
-//     let path = path.as_str().unwrap();
-//     let mut file = File::open(PathBuf::from(path)).unwrap();
-//     let mut buffer = Vec::new();
-
-//     // read the whole file
-//     let _ = file.read_to_end(&mut buffer);
-//     buffer.as_slice()
-// }
-
-fn load_file<'a>(path: Value) -> &'a [u8] {
-    let path = path.as_str().unwrap();
-    let mut file = File::open(PathBuf::from(path)).unwrap();
-    let mut buffer: Box<Vec<u8>> = Box::new(vec![]);
-    file.read_to_end(&mut *buffer).unwrap();
-    Box::leak(buffer)
+/// Reads every `--fallback-font` flag, nushell passes each repetition of the
+/// flag as a named argument of its own. User supplied fonts are searched
+/// before the built-in ones.
+fn resolve_fallback_fonts(
+    engine: &nu_plugin::EngineInterface,
+    call: &EvaluatedCall,
+    font: &mut FontFamily<'static>,
+) -> Result<(), LabeledError> {
+    let mut fallbacks = Vec::new();
+    for (name, value) in &call.named {
+        let Some(value) = value.as_ref().filter(|_| name.item == "fallback-font") else {
+            continue;
+        };
+        fallbacks.push(read_font(engine, value)?);
+    }
+    font.fallbacks.splice(0..0, fallbacks);
+    Ok(())
+}
+
+/// Loads the face a font flag points to.
+fn read_font(
+    engine: &nu_plugin::EngineInterface,
+    value: &Value,
+) -> Result<FontFace<'static>, LabeledError> {
+    let spec = face_spec(engine, value)?;
+    load_font(&spec)
+        .map_err(|err| make_params_err(format!("`{}`: {err}", spec.path.display()), value.span()))
+}
+
+/// Reads a font flag: the path of a font file, or a record with the `path`,
+/// the `index` of the face in a collection, and the `weight` and `italic`
+/// variation axis values. Relative paths start at the current directory of
+/// the shell.
+fn face_spec(engine: &nu_plugin::EngineInterface, value: &Value) -> Result<FaceSpec, LabeledError> {
+    let mut spec = FaceSpec::default();
+    let path = match value {
+        Value::Record { val, .. } => {
+            if let Some(index) = val.get("index") {
+                spec.index = match index.as_int() {
+                    Ok(index) if index >= 0 => index as u32,
+                    _ => {
+                        return Err(make_params_err(
+                            "`index` must be a non-negative integer".to_string(),
+                            index.span(),
+                        ))
+                    }
+                };
+            }
+            let axis = |name: &str| {
+                val.get(name)
+                    .map(|axis| match axis {
+                        Value::Bool { val, .. } => Ok(if *val { 1.0 } else { 0.0 }),
+                        _ => axis
+                            .coerce_float()
+                            .map(|value| value as f32)
+                            .map_err(|err| make_params_err(err.to_string(), axis.span())),
+                    })
+                    .transpose()
+            };
+            spec.weight = axis("weight")?;
+            spec.italic = axis("italic")?;
+            val.get("path").ok_or_else(|| {
+                make_params_err("font record has no `path`".to_string(), value.span())
+            })?
+        }
+        _ => value,
+    };
+    let path = path
+        .as_str()
+        .map_err(|err| make_params_err(err.to_string(), path.span()))?;
+    spec.path = flag_path(engine, path);
+    Ok(spec)
+}
+
+/// Paths given to flags are relative to the current directory of the shell.
+fn flag_path(engine: &nu_plugin::EngineInterface, path: &str) -> PathBuf {
+    match engine.get_current_dir() {
+        Ok(current) => PathBuf::from(current).join(path),
+        Err(_) => PathBuf::from(path),
+    }
 }
 
 fn make_params_err(text: String, span: Span) -> LabeledError {
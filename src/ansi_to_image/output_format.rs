@@ -0,0 +1,198 @@
+//! Formats the output can be written in, chosen by the `--format` flag or the
+//! extension of the output path.
+
+use std::{
+    fmt::{self, Display, Formatter},
+    io::{self, Cursor},
+    path::Path,
+};
+
+use image::{
+    codecs::{
+        bmp::BmpEncoder, jpeg::JpegEncoder, png::PngEncoder, qoi::QoiEncoder, tiff::TiffEncoder,
+        webp::WebPEncoder,
+    },
+    DynamicImage, ImageError, RgbaImage,
+};
+
+use super::{html::render_html, printer::Printer, svg::render_svg};
+
+pub(super) const DEFAULT_JPEG_QUALITY: u8 = 90;
+
+/// Largest image that is rasterised, 1 GiB of RGBA pixels.
+const MAX_IMAGE_PIXELS: u64 = 1 << 28;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum OutputFormat {
+    Png,
+    Jpeg {
+        quality: u8,
+    },
+    /// Always lossless, the only WebP encoding the `image` crate writes.
+    WebP,
+    Bmp,
+    Qoi,
+    Tiff,
+    Svg,
+    Html,
+}
+
+#[derive(Debug)]
+pub(super) enum OutputError {
+    Io(io::Error),
+    Image(ImageError),
+    /// The rasterised image would have more than `MAX_IMAGE_PIXELS` pixels.
+    TooLarge {
+        width: u64,
+        height: u64,
+    },
+}
+
+impl Display for OutputError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            OutputError::Io(err) => write!(f, "{err}"),
+            OutputError::Image(err) => write!(f, "{err}"),
+            OutputError::TooLarge { width, height } => write!(
+                f,
+                "the image would be {width}x{height} pixels, more than the limit of \
+                 {MAX_IMAGE_PIXELS} pixels"
+            ),
+        }
+    }
+}
+
+impl From<io::Error> for OutputError {
+    fn from(err: io::Error) -> Self {
+        OutputError::Io(err)
+    }
+}
+
+impl From<ImageError> for OutputError {
+    fn from(err: ImageError) -> Self {
+        OutputError::Image(err)
+    }
+}
+
+impl OutputFormat {
+    /// The format called `name`, which is also its file extension. JPEG
+    /// output uses the default quality.
+    pub(super) fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "png" => Some(OutputFormat::Png),
+            "jpg" | "jpeg" => Some(OutputFormat::Jpeg {
+                quality: DEFAULT_JPEG_QUALITY,
+            }),
+            "webp" => Some(OutputFormat::WebP),
+            "bmp" => Some(OutputFormat::Bmp),
+            "qoi" => Some(OutputFormat::Qoi),
+            "tif" | "tiff" => Some(OutputFormat::Tiff),
+            "svg" => Some(OutputFormat::Svg),
+            "htm" | "html" => Some(OutputFormat::Html),
+            _ => None,
+        }
+    }
+
+    /// The format of `path`, from its extension.
+    pub(super) fn from_path(path: &Path) -> Option<Self> {
+        Self::from_name(path.extension()?.to_str()?)
+    }
+
+    pub(super) fn list() -> Vec<String> {
+        ["png", "jpeg", "webp", "bmp", "qoi", "tiff", "svg", "html"]
+            .map(str::to_string)
+            .to_vec()
+    }
+
+    /// Extension of the files written when no output path is given.
+    pub(super) fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg { .. } => "jpg",
+            OutputFormat::WebP => "webp",
+            OutputFormat::Bmp => "bmp",
+            OutputFormat::Qoi => "qoi",
+            OutputFormat::Tiff => "tiff",
+            OutputFormat::Svg => "svg",
+            OutputFormat::Html => "html",
+        }
+    }
+
+    fn is_raster(&self) -> bool {
+        !matches!(self, OutputFormat::Svg | OutputFormat::Html)
+    }
+
+    /// Writes the printer's text to `path`, as text for SVG and HTML and
+    /// rasterised for the image formats. Formats without an alpha channel
+    /// drop it. The output is encoded in memory first, so a failure leaves an
+    /// existing file untouched.
+    pub(super) fn write(&self, printer: Printer, path: &Path) -> Result<(), OutputError> {
+        let (width, height) = printer.image_size();
+        if self.is_raster() && width.saturating_mul(height) > MAX_IMAGE_PIXELS {
+            return Err(OutputError::TooLarge { width, height });
+        }
+        let mut file = Cursor::new(Vec::new());
+        let rasterise = |printer| RgbaImage::from(printer);
+        match *self {
+            OutputFormat::Png => {
+                rasterise(printer).write_with_encoder(PngEncoder::new(&mut file))?
+            }
+            OutputFormat::Jpeg { quality } => DynamicImage::ImageRgba8(rasterise(printer))
+                .to_rgb8()
+                .write_with_encoder(JpegEncoder::new_with_quality(&mut file, quality))?,
+            OutputFormat::WebP => {
+                rasterise(printer).write_with_encoder(WebPEncoder::new_lossless(&mut file))?
+            }
+            OutputFormat::Bmp => {
+                rasterise(printer).write_with_encoder(BmpEncoder::new(&mut file))?
+            }
+            OutputFormat::Qoi => {
+                rasterise(printer).write_with_encoder(QoiEncoder::new(&mut file))?
+            }
+            OutputFormat::Tiff => {
+                rasterise(printer).write_with_encoder(TiffEncoder::new(&mut file))?
+            }
+            OutputFormat::Svg => file.get_mut().extend(render_svg(&printer).into_bytes()),
+            OutputFormat::Html => file.get_mut().extend(render_html(&printer).into_bytes()),
+        }
+        Ok(std::fs::write(path, file.into_inner())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_and_aliases() {
+        assert_eq!(OutputFormat::from_name("PNG"), Some(OutputFormat::Png));
+        assert_eq!(
+            OutputFormat::from_name("jpg"),
+            Some(OutputFormat::Jpeg {
+                quality: DEFAULT_JPEG_QUALITY
+            })
+        );
+        assert_eq!(OutputFormat::from_name("tif"), Some(OutputFormat::Tiff));
+        assert_eq!(OutputFormat::from_name("htm"), Some(OutputFormat::Html));
+        assert_eq!(OutputFormat::from_name("gif"), None);
+        assert_eq!(OutputFormat::from_name(""), None);
+    }
+
+    #[test]
+    fn listed_names_and_extensions_round_trip() {
+        for name in OutputFormat::list() {
+            let format = OutputFormat::from_name(&name).unwrap();
+            assert_eq!(OutputFormat::from_name(format.extension()), Some(format));
+        }
+    }
+
+    #[test]
+    fn formats_of_paths() {
+        assert_eq!(
+            OutputFormat::from_path(Path::new("out/image.Svg")),
+            Some(OutputFormat::Svg)
+        );
+        assert_eq!(OutputFormat::from_path(Path::new("image")), None);
+        assert_eq!(OutputFormat::from_path(Path::new("image.txt")), None);
+    }
+}
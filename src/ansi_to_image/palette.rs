@@ -4,7 +4,7 @@ use crate::warn;
 
 use crate::ansi_to_image::color::{Color, ColorType};
 type ColorOption = Option<[u8; 4]>;
-#[allow(dead_code)]
+#[allow(dead_code, clippy::large_enum_variant)]
 #[derive(Debug)]
 pub enum Palette {
     Vscode,
@@ -24,10 +24,10 @@ pub enum Palette {
 
 impl Default for Palette {
     fn default() -> Self {
-        if let Ok(_) = std::env::var("NU_PLUGIN_IMAGE_FG") {
+        if std::env::var("NU_PLUGIN_IMAGE_FG").is_ok() {
             return Palette::Env;
         }
-        return Palette::Vscode;
+        Palette::Vscode
     }
 }
 
@@ -57,6 +57,7 @@ pub struct PaletteData {
     pub fixed: [[u8; 4]; 256],
 }
 impl PaletteData {
+    #[allow(clippy::too_many_arguments)]
     pub fn copy_with(
         &self,
         primary_foreground: ColorOption,
@@ -867,7 +868,7 @@ pub(crate) fn strhex_to_rgba(hex: String) -> Option<[u8; 4]> {
 
     let has_alpha = hex.len() == 8;
 
-    if let Ok(hex) = i64::from_str_radix(&hex, 16) {
+    if let Ok(hex) = i64::from_str_radix(hex, 16) {
         let alpha = if has_alpha {
             Some((hex >> 24) as u8)
         } else {
@@ -1,59 +1,137 @@
 use crate::{trace, warn};
-use ab_glyph::{Font, FontRef, Glyph, Point};
+use std::ops::Range;
+
+use ab_glyph::{point, Font, GlyphId, ScaleFont};
 use image::{Rgba, RgbaImage};
-use imageproc::drawing::draw_text_mut;
-use std::collections::BTreeMap;
+use imageproc::pixelops::weighted_sum;
 use vte::{Params, Perform};
 
 use crate::ansi_to_image::{color::ColorType, escape_parser::EscapeSequence, palette::Palette};
 
-use super::internal_scale::InternalScale;
+use super::{
+    bitmap_font::{BitmapFont, BitmapGlyph},
+    box_drawing::{draw_box_character, is_box_character, CellRect},
+    cell_width::{
+        is_emoji_modifier, is_regional_indicator, AmbiguousWidth, VARIATION_SELECTOR_16,
+        ZERO_WIDTH_JOINER,
+    },
+    charset::Charset,
+    color_glyph::{has_color_glyph, render_color_glyph, ColorGlyph, GlyphBox},
+    font_cache::FontFace,
+    grid::{
+        Attributes, Cell, Grid, LineSize, UnderlineStyle, VerticalPosition, MAX_COLUMNS, MAX_ROWS,
+    },
+    internal_scale::InternalScale,
+    shaping::Shaper,
+    synthetic_style::{draw_synthetic_glyph, Synthesis},
+    tab_stops::TabStops,
+};
 
 pub(super) struct Settings<'a> {
-    pub(super) font: FontRef<'a>,
-    pub(super) font_bold: FontRef<'a>,
-    pub(super) font_italic: FontRef<'a>,
-    pub(super) font_italic_bold: FontRef<'a>,
+    pub(super) font: FontFace<'a>,
+    /// Styled faces, styles without a face are synthesized.
+    pub(super) font_bold: Option<FontFace<'a>>,
+    pub(super) font_italic: Option<FontFace<'a>>,
+    pub(super) font_italic_bold: Option<FontFace<'a>>,
+    pub(super) fallback_fonts: Vec<FontFace<'a>>,
     pub(super) font_height: f32,
     pub(super) scale: InternalScale,
+    /// Distance between lines, as a multiple of the font height.
+    pub(super) line_height: f32,
+    /// Space added to the width of each cell, in pixels.
+    pub(super) letter_spacing: f32,
     pub(super) palette: Palette,
     pub(super) png_width: Option<u32>,
+    pub(super) tab_width: usize,
+    pub(super) ambiguous_width: AmbiguousWidth,
+    /// Shape runs of text with the font's OpenType features, drawing its ligatures.
+    pub(super) ligatures: bool,
+    /// Embed the faces in SVG output instead of naming their families.
+    pub(super) embed_fonts: bool,
+    /// Drawn instead of the outline fonts for the characters it has.
+    pub(super) bitmap_font: Option<BitmapFont>,
 }
 
 #[derive(Debug, Default)]
-struct SettingsInternal {
-    glyph_advance_width: f32,
-    new_line_distance: u32,
-    png_width: Option<u32>,
+pub(super) struct SettingsInternal {
+    pub(super) glyph_advance_width: f32,
+    pub(super) new_line_distance: u32,
+    columns: Option<usize>,
+    /// Distance of the baseline from the top of a cell, in pixels.
+    pub(super) baseline: f32,
+    /// Distance of the underline's top edge below the baseline, in pixels.
+    pub(super) underline_position: f32,
+    pub(super) underline_thickness: f32,
+    /// Distance of the strikethrough's top edge above the baseline, in pixels.
+    pub(super) strikeout_position: f32,
+    pub(super) strikeout_thickness: f32,
+    superscript: ScriptMetrics,
+    subscript: ScriptMetrics,
+    /// Size of a bitmap font pixel, and the bitmap font's ascent, in image
+    /// pixels.
+    bitmap_pixel: f32,
+    bitmap_ascent: f32,
 }
 
-#[derive(Debug)]
-struct TextEntry {
-    character: char,
-    foreground_color: ColorType,
-    background_color: ColorType,
-    font: FontState,
-    underline: bool,
+/// Size and placement of superscript or subscript glyphs.
+#[derive(Debug, Default, Clone, Copy)]
+pub(super) struct ScriptMetrics {
+    /// Glyph scale relative to the regular text.
+    pub(super) scale: f32,
+    /// Distance the baseline is raised by, in pixels (negative lowers it).
+    pub(super) offset: f32,
 }
 
-#[derive(Debug, Clone, Copy)]
-enum FontState {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum FontState {
     Normal,
     Bold,
     Italic,
     ItalicBold,
 }
 
-#[derive(Debug)]
+/// Terminal state, all positions are in cells. Pixel positions are only
+/// computed when the grid is rasterised.
+#[derive(Debug, Default)]
 struct State {
-    text: BTreeMap<(u32, u32), TextEntry>,
-    current_x: u32,
-    current_y: u32,
-    foreground_color: ColorType,
-    background_color: ColorType,
-    font: FontState,
+    grid: Grid,
+    cursor_x: usize,
+    cursor_y: usize,
+    /// Set after printing into the last column, the wrap happens on the next
+    /// printed character like in VT100 compatible terminals.
+    wrap_pending: bool,
+    attributes: Attributes,
+    tab_stops: TabStops,
+    /// Set by a backspace, the next printed character overstrikes the cell
+    /// under the cursor like on a line printer (`_\bX` underlines, `X\bX` bolds).
+    overstrike_pending: bool,
+    saved_cursor: Option<SavedCursor>,
+    /// G0 and G1 character sets
+    charsets: [Charset; 2],
+    /// Index of the charset shifted in with SI (G0) or SO (G1)
+    active_charset: usize,
     last_execute_byte: Option<u8>,
-    underline: bool,
+    /// Set once the grid has scrolled past `MAX_ROWS`.
+    scrolled: bool,
+}
+
+impl State {
+    fn new(tab_width: usize) -> Self {
+        Self {
+            tab_stops: TabStops::new(tab_width),
+            ..Self::default()
+        }
+    }
+}
+
+/// Cursor state stored by `DECSC` (`ESC 7`) and restored by `DECRC` (`ESC 8`).
+#[derive(Debug, Clone, Copy)]
+struct SavedCursor {
+    x: usize,
+    y: usize,
+    attributes: Attributes,
+    charsets: [Charset; 2],
+    active_charset: usize,
 }
 
 pub(super) struct Printer<'a> {
@@ -63,83 +141,649 @@ pub(super) struct Printer<'a> {
 }
 
 pub(super) fn new(settings: Settings) -> Printer {
-    let glyph_advance_width = settings
-        .font
-        .glyph_bounds(&Glyph {
-            id: settings.font.glyph_id('_'),
-            scale: settings.scale.into(),
-            position: Point { x: 0.0, y: 0.0 },
-        })
-        .width();
+    let scaled_font = settings.font.as_scaled(settings.scale);
+    // Cells are as wide as the widest printable ASCII character, which is the
+    // advance of every character in a monospace font
+    let glyph_advance_width = (' '..='~')
+        .map(|character| scaled_font.h_advance(scaled_font.glyph_id(character)))
+        .fold(0.0, f32::max);
+    // Bitmap fonts set the cell size instead, at the integer scale closest to
+    // the font size
+    let bitmap_pixel = settings.bitmap_font.as_ref().map(|bitmap| {
+        (settings.font_height / bitmap.height as f32)
+            .round()
+            .max(1.0)
+    });
+    let glyph_advance_width = match (&settings.bitmap_font, bitmap_pixel) {
+        (Some(bitmap), Some(pixel)) => bitmap.width as f32 * pixel,
+        _ => glyph_advance_width,
+    };
+    let glyph_advance_width = (glyph_advance_width + settings.letter_spacing).max(1.0);
 
-    let new_line_distance = settings.font_height as u32;
+    let (text_height, line_gap) = match (&settings.bitmap_font, bitmap_pixel) {
+        (Some(bitmap), Some(pixel)) => (bitmap.height as f32 * pixel, 0.0),
+        _ => (
+            scaled_font.ascent() - scaled_font.descent(),
+            scaled_font.line_gap(),
+        ),
+    };
+    let new_line_distance =
+        (((text_height + line_gap) * settings.line_height).round() as u32).max(1);
+    // The line gap and any extra line height are split evenly above and below the text
+    let half_leading = (new_line_distance as f32 - text_height) / 2.0;
 
-    let png_width = settings.png_width;
+    let columns = settings
+        .png_width
+        .map(|width| ((width as f32 / glyph_advance_width) as usize).max(1));
+
+    let v_scale = scaled_font.v_scale_factor();
+    let baseline = scaled_font.ascent();
+    let face = settings.font.face();
+    let (underline_position, underline_thickness) =
+        match face.and_then(|face| face.underline_metrics()) {
+            Some(metrics) => (
+                -metrics.position as f32 * v_scale,
+                metrics.thickness as f32 * v_scale,
+            ),
+            None => (-scaled_font.descent() / 2.0, settings.font_height / 20.0),
+        };
+    // The strike goes through the middle of the x-height
+    let x_height = face
+        .and_then(|face| face.x_height())
+        .map(|x_height| x_height as f32 * v_scale)
+        .unwrap_or(baseline / 2.0);
+    let strikeout_thickness = face
+        .and_then(|face| face.strikeout_metrics())
+        .map(|metrics| metrics.thickness as f32 * v_scale)
+        .unwrap_or(underline_thickness)
+        .max(1.0);
+    let strikeout_position = x_height / 2.0 + strikeout_thickness / 2.0;
+    let script_metrics = |metrics: Option<ttf_parser::ScriptMetrics>, default_offset: f32| {
+        let units_per_em = face.map_or(1.0, |face| face.units_per_em() as f32);
+        match metrics {
+            Some(metrics) if metrics.y_size > 0 => ScriptMetrics {
+                scale: (metrics.y_size as f32 / units_per_em).clamp(0.3, 1.0),
+                offset: metrics.y_offset as f32 * v_scale,
+            },
+            _ => ScriptMetrics {
+                scale: 0.6,
+                offset: default_offset,
+            },
+        }
+    };
+    let superscript = script_metrics(
+        face.and_then(|face| face.superscript_metrics()),
+        baseline * 0.4,
+    );
+    let mut subscript = script_metrics(
+        face.and_then(|face| face.subscript_metrics()),
+        baseline * 0.15,
+    );
+    // OS/2 stores the subscript offset as a positive distance below the baseline
+    subscript.offset = -subscript.offset;
+
+    let bitmap_ascent = match (&settings.bitmap_font, bitmap_pixel) {
+        (Some(bitmap), Some(pixel)) => bitmap.ascent as f32 * pixel,
+        _ => 0.0,
+    };
+    // Bitmap decorations are whole font pixels: the underline on the bottom
+    // row and the strike through the lower half of the ascent
+    let (
+        baseline,
+        underline_position,
+        underline_thickness,
+        strikeout_position,
+        strikeout_thickness,
+    ) = match (&settings.bitmap_font, bitmap_pixel) {
+        (Some(bitmap), Some(pixel)) => {
+            let descent = bitmap.height - bitmap.ascent;
+            (
+                bitmap_ascent,
+                descent.saturating_sub(1) as f32 * pixel,
+                pixel,
+                (bitmap.ascent * 2 / 5).max(1) as f32 * pixel,
+                pixel,
+            )
+        }
+        _ => (
+            baseline,
+            underline_position,
+            underline_thickness,
+            strikeout_position,
+            strikeout_thickness,
+        ),
+    };
 
     let settings_internal = SettingsInternal {
         glyph_advance_width,
         new_line_distance,
-        png_width,
+        columns,
+        baseline: baseline + half_leading,
+        underline_position,
+        underline_thickness: underline_thickness.max(1.0),
+        strikeout_position,
+        strikeout_thickness,
+        superscript,
+        subscript,
+        bitmap_pixel: bitmap_pixel.unwrap_or(1.0),
+        bitmap_ascent,
     };
 
+    let state = State::new(settings.tab_width);
+
     Printer {
         settings,
         settings_internal,
-        state: State::default(),
+        state,
     }
 }
 
-impl Default for State {
-    fn default() -> Self {
-        Self {
-            text: BTreeMap::new(),
-            current_x: 0,
-            current_y: 0,
-            foreground_color: ColorType::PrimaryForeground,
-            background_color: ColorType::PrimaryBackground,
-            font: FontState::Normal,
-            last_execute_byte: None,
-            underline: false,
+impl Printer<'_> {
+    /// Columns available on `row`, double width rows hold half as many cells.
+    fn line_columns(&self, row: usize) -> Option<usize> {
+        let width_factor = self.state.grid.line_size(row).width_factor();
+        self.settings_internal
+            .columns
+            .map(|columns| (columns / width_factor).max(1))
+    }
+
+    /// Columns `row` wraps at, `MAX_COLUMNS` when there is no fixed width.
+    fn wrap_columns(&self, row: usize) -> usize {
+        let width_factor = self.state.grid.line_size(row).width_factor();
+        self.line_columns(row)
+            .unwrap_or((MAX_COLUMNS / width_factor).max(1))
+    }
+
+    fn move_cursor_to(&mut self, x: usize, y: usize) {
+        let y = y.min(MAX_ROWS - 1);
+        self.state.cursor_x = x.min(self.wrap_columns(y) - 1);
+        self.state.cursor_y = y;
+        self.state.wrap_pending = false;
+    }
+
+    fn move_cursor_by(&mut self, x: i64, y: i64) {
+        let new_x = (self.state.cursor_x as i64 + x).max(0) as usize;
+        let new_y = (self.state.cursor_y as i64 + y).max(0) as usize;
+        self.move_cursor_to(new_x, new_y);
+    }
+
+    /// IND - Index, moves the cursor down a row. On the last row the grid
+    /// scrolls up instead, like a terminal at the bottom of its screen.
+    fn index(&mut self) {
+        let y = self.state.cursor_y + 1;
+        if y < MAX_ROWS {
+            self.move_cursor_to(self.state.cursor_x, y);
+            return;
+        }
+        if !self.state.scrolled {
+            warn!("the output is longer than {MAX_ROWS} lines, its first lines are dropped");
+            self.state.scrolled = true;
+        }
+        self.state.grid.scroll_up();
+        self.move_cursor_to(self.state.cursor_x, self.state.cursor_y);
+    }
+
+    /// Moves the cursor to the start of the next row.
+    fn new_line(&mut self) {
+        self.index();
+        self.move_cursor_to(0, self.state.cursor_y);
+    }
+
+    /// Moves the cursor past `width` printed columns, the wrap to the next line
+    /// is left pending when they reach the end of the line.
+    fn advance_cursor(&mut self, width: usize) {
+        let last = self.state.cursor_x + width - 1;
+        let columns = self.wrap_columns(self.state.cursor_y);
+        if last + 1 >= columns {
+            self.state.cursor_x = last.min(columns - 1);
+            self.state.wrap_pending = true;
+        } else {
+            self.state.cursor_x = last + 1;
+        }
+    }
+
+    /// Appends zero width characters, emoji modifiers, characters following a
+    /// zero width joiner and the second regional indicator of a flag to the
+    /// character before the cursor, so the grapheme cluster is drawn in a
+    /// single cell. Returns `false` when `character` starts a new cell.
+    fn combine_with_previous(&mut self, character: char, width: usize) -> bool {
+        let (x, y) = (self.state.cursor_x, self.state.cursor_y);
+        let column = if self.state.wrap_pending {
+            Some(x)
+        } else {
+            x.checked_sub(1)
+        };
+        let Some(cells) = self.state.grid.rows.get_mut(y).map(|row| &mut row.cells) else {
+            return false;
+        };
+        let Some(mut column) = column.filter(|column| *column < cells.len()) else {
+            return false;
+        };
+        if cells[column].width == 0 && column > 0 {
+            column -= 1;
+        }
+        let cell = &mut cells[column];
+        let flag = is_regional_indicator(character)
+            && is_regional_indicator(cell.character)
+            && cell.combining.is_empty();
+        let extends = width == 0
+            || is_emoji_modifier(character)
+            || cell.combining.last() == Some(&ZERO_WIDTH_JOINER);
+        if !flag && !extends {
+            return false;
+        }
+        cell.combining.push(character);
+
+        // VS16 selects the emoji presentation and a pair of regional
+        // indicators is a flag, both two columns wide
+        let widens = character == VARIATION_SELECTOR_16 || flag;
+        if widens && cell.width == 1 && !self.state.wrap_pending {
+            cell.width = 2;
+            let attributes = cell.attributes;
+            self.state.grid.split_wide(x, y);
+            *self.state.grid.cell_mut(x, y) = Cell::spacer(attributes);
+            self.advance_cursor(1);
+        }
+        true
+    }
+
+    /// DECSC - Save Cursor
+    fn save_cursor(&mut self) {
+        self.state.saved_cursor = Some(SavedCursor {
+            x: self.state.cursor_x,
+            y: self.state.cursor_y,
+            attributes: self.state.attributes,
+            charsets: self.state.charsets,
+            active_charset: self.state.active_charset,
+        });
+    }
+
+    /// DECRC - Restore Cursor, moves home with default attributes when nothing
+    /// was saved.
+    fn restore_cursor(&mut self) {
+        let saved = self.state.saved_cursor.unwrap_or(SavedCursor {
+            x: 0,
+            y: 0,
+            attributes: Attributes::default(),
+            charsets: Default::default(),
+            active_charset: 0,
+        });
+        self.move_cursor_to(saved.x, saved.y);
+        self.state.attributes = saved.attributes;
+        self.state.charsets = saved.charsets;
+        self.state.active_charset = saved.active_charset;
+    }
+
+    /// DECSWL, DECDWL and DECDHL - sets the size of the cursor's row
+    fn set_line_size(&mut self, size: LineSize) {
+        self.state.grid.row_mut(self.state.cursor_y).size = size;
+        self.move_cursor_to(self.state.cursor_x, self.state.cursor_y);
+    }
+
+    /// RI - Reverse Index, scrolls the content down when the cursor is already
+    /// on the first row.
+    fn reverse_index(&mut self) {
+        if self.state.cursor_y == 0 {
+            self.state.grid.insert_row(0);
+            self.state.wrap_pending = false;
+        } else {
+            self.move_cursor_by(0, -1);
+        }
+    }
+
+    /// Moves the cursor to the `count`th next tab stop, or to the last column
+    /// if there are no more tab stops.
+    fn horizontal_tab(&mut self, count: u16) {
+        let mut column = self.state.cursor_x;
+        for _ in 0..count {
+            match self.state.tab_stops.next(column) {
+                Some(next) => column = next,
+                None => {
+                    let columns = self.line_columns(self.state.cursor_y);
+                    column = columns.map_or(column, |c| c - 1);
+                    break;
+                }
+            }
+        }
+        self.move_cursor_to(column, self.state.cursor_y);
+    }
+
+    /// Where erasing to the end of `row` stops: the end of the line when the output
+    /// has a fixed width, otherwise the end of the row's cells.
+    fn erase_end(&self, row: usize) -> usize {
+        self.line_columns(row).unwrap_or_else(|| {
+            self.state
+                .grid
+                .rows
+                .get(row)
+                .map_or(0, |row| row.cells.len())
+        })
+    }
+
+    /// EL - Erase in Line
+    fn erase_in_line(&mut self, mode: u16) {
+        let blank = Cell::blank(self.state.attributes);
+        let (x, y) = (self.state.cursor_x, self.state.cursor_y);
+        let end = self.erase_end(y);
+        match mode {
+            0 => self.state.grid.erase_columns(y, x, end, &blank),
+            1 => self.state.grid.erase_columns(y, 0, x + 1, &blank),
+            2 => self.state.grid.erase_columns(y, 0, end, &blank),
+            _ => trace!("[erase_in_line] unknown mode {mode}"),
+        }
+    }
+
+    /// ED - Erase in Display
+    fn erase_in_display(&mut self, mode: u16) {
+        let blank = Cell::blank(self.state.attributes);
+        let (x, y) = (self.state.cursor_x, self.state.cursor_y);
+        let (end, columns) = (self.erase_end(y), self.settings_internal.columns);
+        let grid = &mut self.state.grid;
+        match mode {
+            0 => {
+                grid.erase_columns(y, x, end, &blank);
+                grid.erase_rows(y + 1, usize::MAX, columns, &blank);
+            }
+            1 => {
+                grid.erase_rows(0, y, columns, &blank);
+                grid.erase_columns(y, 0, x + 1, &blank);
+            }
+            2 => grid.erase_rows(0, usize::MAX, columns, &blank),
+            // ED 3 clears the scrollback, which the captured output does not have
+            3 => trace!("[erase_in_display] no scrollback to erase"),
+            _ => trace!("[erase_in_display] unknown mode {mode}"),
+        }
+    }
+
+    fn select_graphic_rendition(&mut self, params: &Params) {
+        let actions = EscapeSequence::parse_params(params.iter().collect::<Vec<_>>());
+        let attributes = &mut self.state.attributes;
+
+        for action in actions {
+            match action {
+                EscapeSequence::Reset => *attributes = Attributes::default(),
+
+                EscapeSequence::Bold => attributes.font += FontState::Bold,
+                EscapeSequence::Italic => attributes.font += FontState::Italic,
+                EscapeSequence::Underline(style) => attributes.underline = style,
+
+                EscapeSequence::Faint => attributes.faint = true,
+                EscapeSequence::NormalIntensity => {
+                    attributes.font -= FontState::Bold;
+                    attributes.faint = false;
+                }
+                EscapeSequence::ReverseVideo => attributes.reverse = true,
+                EscapeSequence::NotReversed => attributes.reverse = false,
+                EscapeSequence::Conceal => attributes.conceal = true,
+                EscapeSequence::Reveal => attributes.conceal = false,
+                EscapeSequence::CrossedOut => attributes.crossed_out = true,
+                EscapeSequence::NotCrossedOut => attributes.crossed_out = false,
+                EscapeSequence::Overline => attributes.overline = true,
+                EscapeSequence::NotOverline => attributes.overline = false,
+                EscapeSequence::Superscript => {
+                    attributes.vertical_position = VerticalPosition::Superscript
+                }
+                EscapeSequence::Subscript => {
+                    attributes.vertical_position = VerticalPosition::Subscript
+                }
+                EscapeSequence::NeitherSuperscriptNorSubscript => {
+                    attributes.vertical_position = VerticalPosition::Normal
+                }
+                EscapeSequence::NotItalicNorBlackLetter => attributes.font -= FontState::Italic,
+                EscapeSequence::NotUnderline => attributes.underline = UnderlineStyle::None,
+
+                EscapeSequence::ForegroundColor(color_type) => attributes.foreground = color_type,
+                EscapeSequence::BackgroundColor(color_type) => attributes.background = color_type,
+
+                EscapeSequence::DefaultForegroundColor => {
+                    attributes.foreground = ColorType::PrimaryForeground
+                }
+
+                EscapeSequence::DefaultBackgroundColor => {
+                    attributes.background = ColorType::PrimaryBackground
+                }
+
+                EscapeSequence::UnderlineColor(color_type) => {
+                    attributes.underline_color = Some(color_type)
+                }
+                EscapeSequence::DefaultUnderlineColor => attributes.underline_color = None,
+
+                EscapeSequence::BlackLetterFont
+                | EscapeSequence::SlowBlink
+                | EscapeSequence::NotBlinking
+                | EscapeSequence::PrimaryFont
+                | EscapeSequence::SetAlternativeFont
+                | EscapeSequence::DisableProportionalSpacing
+                | EscapeSequence::RapidBlink => {
+                    warn!("not implemented for action: {action:?}")
+                }
+                EscapeSequence::Unimplemented(value) => {
+                    warn!("not implemented for value: {value:?}")
+                }
+                EscapeSequence::Ignore => trace!("ignored sequence"),
+            }
+        }
+    }
+}
+
+impl Printer<'_> {
+    /// The font of `style`, or the first fallback font that has a glyph for
+    /// `character` when it does not. Characters no font has are drawn with
+    /// the font of `style`.
+    fn font_for(&self, style: FontState, character: char) -> &FontFace<'_> {
+        let settings = &self.settings;
+        let font = match style {
+            FontState::Normal => None,
+            FontState::Bold => settings.font_bold.as_ref(),
+            FontState::Italic => settings.font_italic.as_ref(),
+            FontState::ItalicBold => settings
+                .font_italic_bold
+                .as_ref()
+                .or(settings.font_italic.as_ref())
+                .or(settings.font_bold.as_ref()),
+        }
+        .unwrap_or(&settings.font);
+        if font.glyph_id(character).0 != 0 {
+            return font;
+        }
+        self.settings
+            .fallback_fonts
+            .iter()
+            .find(|fallback| fallback.glyph_id(character).0 != 0)
+            .unwrap_or(font)
+    }
+
+    /// The parts of `style` that `font` does not provide and are synthesized,
+    /// `font` being one returned by `font_for`.
+    fn synthesis(&self, style: FontState, font: &FontFace) -> Synthesis {
+        let is =
+            |face: &Option<FontFace>| face.as_ref().is_some_and(|face| std::ptr::eq(face, font));
+        let settings = &self.settings;
+        Synthesis {
+            bold: matches!(style, FontState::Bold | FontState::ItalicBold)
+                && !is(&settings.font_bold)
+                && !is(&settings.font_italic_bold),
+            italic: matches!(style, FontState::Italic | FontState::ItalicBold)
+                && !is(&settings.font_italic)
+                && !is(&settings.font_italic_bold),
+        }
+    }
+
+    /// The first of the font of `style` and the fallback fonts that has a color
+    /// glyph for `character`.
+    fn color_font_for(&self, style: FontState, character: char) -> Option<&FontFace<'_>> {
+        std::iter::once(self.font_for(style, character))
+            .chain(&self.settings.fallback_fonts)
+            .find(|font| has_color_glyph(font, character))
+    }
+
+    /// The glyph of the bitmap font for `character`, if there is one.
+    fn bitmap_glyph(&self, character: char) -> Option<&BitmapGlyph> {
+        self.settings.bitmap_font.as_ref()?.glyph(character)
+    }
+
+    /// The font a cell is shaped with, `None` for cells drawn on their own:
+    /// blanks, wide characters, emoji, and characters drawn geometrically or
+    /// from the bitmap font.
+    fn shaping_font(&self, cell: &Cell) -> Option<&FontFace<'_>> {
+        if cell.width != 1
+            || cell.character == ' '
+            || cell.attributes.conceal
+            || cell.combining.contains(&VARIATION_SELECTOR_16)
+            || is_box_character(cell.character)
+            || self.bitmap_glyph(cell.character).is_some()
+        {
+            return None;
+        }
+        let font = self.font_for(cell.attributes.font, cell.character);
+        (!has_color_glyph(font, cell.character)).then_some(font)
+    }
+
+    /// Resolves the foreground and background colors of a cell, applying
+    /// reverse video and faint (drawn as the foreground at half opacity over
+    /// the background).
+    pub(super) fn colors(&self, attributes: &Attributes) -> (Rgba<u8>, Rgba<u8>) {
+        let palette = &self.settings.palette;
+        let foreground_color = Rgba(palette.get_color(attributes.foreground));
+        let background_color = Rgba(palette.get_color(attributes.background));
+        let (mut foreground, background) = if attributes.reverse {
+            (background_color, foreground_color)
+        } else {
+            (foreground_color, background_color)
+        };
+        if attributes.faint {
+            foreground = weighted_sum(foreground, background, 0.5, 0.5);
+        }
+        (foreground, background)
+    }
+}
+
+/// The parsed text and its layout, for backends that write text instead of
+/// rasterising it.
+impl<'a> Printer<'a> {
+    /// Width and height of the rasterised grid, in pixels.
+    pub(super) fn image_size(&self) -> (u64, u64) {
+        let grid = &self.state.grid;
+        let columns = grid.columns().max(1) as f32;
+        let width = (columns * self.settings_internal.glyph_advance_width).round() as u64;
+        let height =
+            grid.rows.len().max(1) as u64 * self.settings_internal.new_line_distance as u64;
+        (width, height)
+    }
+
+    pub(super) fn grid(&self) -> &Grid {
+        &self.state.grid
+    }
+
+    pub(super) fn settings(&self) -> &Settings<'a> {
+        &self.settings
+    }
+
+    pub(super) fn metrics(&self) -> &SettingsInternal {
+        &self.settings_internal
+    }
+
+    pub(super) fn script_metrics(&self, position: VerticalPosition) -> ScriptMetrics {
+        match position {
+            VerticalPosition::Normal => ScriptMetrics {
+                scale: 1.0,
+                offset: 0.0,
+            },
+            VerticalPosition::Superscript => self.settings_internal.superscript,
+            VerticalPosition::Subscript => self.settings_internal.subscript,
         }
     }
 }
 
+/// Returns the first value of the parameter at `index`, treating a missing or
+/// zero value as `default` like terminals do for cursor movement sequences.
+fn param_or(params: &Params, index: usize, default: u16) -> u16 {
+    match params.iter().nth(index).and_then(|param| param.first()) {
+        Some(0) | None => default,
+        Some(value) => *value,
+    }
+}
+
 impl<'a> Perform for Printer<'a> {
     fn print(&mut self, character: char) {
-        self.state.text.insert(
-            (self.state.current_x, self.state.current_y),
-            TextEntry {
-                character,
-                foreground_color: self.state.foreground_color,
-                background_color: self.state.background_color,
-                font: self.state.font,
-                underline: self.state.underline,
-            },
-        );
+        let character = self.state.charsets[self.state.active_charset].map(character);
+        let width = self.settings.ambiguous_width.char_width(character);
+        if self.combine_with_previous(character, width) {
+            return;
+        }
+        // Stand alone combining marks get a cell of their own
+        let width = width.max(1);
 
-        self.state.current_x += self.settings_internal.glyph_advance_width as u32;
+        // Wide characters that do not fit in the rest of the line wrap early
+        let columns = self.wrap_columns(self.state.cursor_y);
+        if width <= columns && self.state.cursor_x + width > columns {
+            self.state.wrap_pending = true;
+        }
+        if self.state.wrap_pending {
+            self.new_line();
+        }
 
-        if let Some(png_width) = self.settings_internal.png_width {
-            if self.state.current_x > png_width {
-                self.state.current_x = 0;
-                self.state.current_y += self.settings_internal.new_line_distance;
+        let (x, y) = (self.state.cursor_x, self.state.cursor_y);
+        for column in x..x + width {
+            self.state.grid.split_wide(column, y);
+        }
+        let overstrike = std::mem::take(&mut self.state.overstrike_pending);
+        let cell = self.state.grid.cell_mut(x, y);
+        let mut attributes = self.state.attributes;
+        let character = match (overstrike, cell.character, character) {
+            (true, '_', character) | (true, character, '_') if character != ' ' => {
+                attributes.underline = UnderlineStyle::Single;
+                character
+            }
+            (true, previous, character) if previous == character => {
+                attributes.font += FontState::Bold;
+                character
             }
+            (_, _, character) => character,
+        };
+        *cell = Cell {
+            character,
+            combining: Vec::new(),
+            width,
+            attributes,
+        };
+        for column in x + 1..x + width {
+            *self.state.grid.cell_mut(column, y) = Cell::spacer(attributes);
         }
+
+        self.advance_cursor(width);
     }
 
     fn execute(&mut self, byte: u8) {
+        self.state.overstrike_pending = false;
         match byte {
+            // ^G 	0x07 	BEL 	Bell 	Makes an audible noise.
+            0x07 => trace!("[execute] bell"),
+
+            // ^H 	0x08 	BS 	Backspace 	Moves the cursor left (but may "backwards wrap" if cursor is at
+            // start of line).
+            0x08 => {
+                self.move_cursor_by(-1, 0);
+                self.state.overstrike_pending = true;
+            }
+
+            // ^I 	0x09 	HT 	Tab 	Moves the cursor right to next multiple of 8.
+            0x09 => self.horizontal_tab(1),
+
+            // ^N 	0x0E 	SO 	Shift Out 	Switches to the G1 character set.
+            0x0e => self.state.active_charset = 1,
+
+            // ^O 	0x0F 	SI 	Shift In 	Switches to the G0 character set.
+            0x0f => self.state.active_charset = 0,
+
             // ^M 	0x0D 	CR 	Carriage Return 	Moves the cursor to column zero.
             0x0d => {
-                self.state.current_x = 0;
+                self.move_cursor_to(0, self.state.cursor_y);
             }
 
             // ^J 	0x0A 	LF 	Line Feed 	Moves to next line, scrolls the display up if at bottom of the
             // screen. Usually does not move horizontally, though programs should not rely on this.
-            0x0a => {
-                self.state.current_x = 0;
-                self.state.current_y += self.settings_internal.new_line_distance;
-            }
+            0x0a => self.new_line(),
 
             _ => trace!("[execute] {byte}, {byte:02x}"),
         }
@@ -166,92 +810,127 @@ impl<'a> Perform for Printer<'a> {
         trace!("[osc_dispatch] params={params:?} bell_terminated={bell_terminated}2");
     }
 
-    fn csi_dispatch(&mut self, params: &Params, _intermediates: &[u8], _ignore: bool, _c: char) {
-        // trace!(
-        //     "[csi_dispatch] params={params:?}, intermediates={intermediates:?}, ignore={ignore:?}, char={c:?}"
-        // );
-        let actions = EscapeSequence::parse_params(params.iter().flatten().collect::<Vec<_>>());
-
-        for action in actions {
-            match action {
-                EscapeSequence::Reset => {
-                    let defaults = State::default();
-
-                    self.state.foreground_color = defaults.foreground_color;
-                    self.state.background_color = defaults.background_color;
-                    self.state.font = defaults.font;
-                    self.state.underline = false;
-                }
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], ignore: bool, c: char) {
+        if ignore || !intermediates.is_empty() {
+            trace!(
+                "[csi_dispatch] ignored params={params:?}, intermediates={intermediates:?}, ignore={ignore:?}, char={c:?}"
+            );
+            return;
+        }
 
-                EscapeSequence::Bold => self.state.font += FontState::Bold,
-                EscapeSequence::Italic => self.state.font += FontState::Italic,
-                EscapeSequence::Underline => self.state.underline = true,
+        match c {
+            'm' => self.select_graphic_rendition(params),
 
-                EscapeSequence::NotBold => self.state.font -= FontState::Bold,
-                EscapeSequence::NotItalicNorBlackLetter => self.state.font -= FontState::Italic,
-                EscapeSequence::NotUnderline => self.state.underline = false,
+            // CUU - Cursor Up
+            'A' => self.move_cursor_by(0, -(param_or(params, 0, 1) as i64)),
+            // CUD - Cursor Down / VPR - Line Position Relative
+            'B' | 'e' => self.move_cursor_by(0, param_or(params, 0, 1) as i64),
+            // CUF - Cursor Forward / HPR - Character Position Relative
+            'C' | 'a' => self.move_cursor_by(param_or(params, 0, 1) as i64, 0),
+            // CUB - Cursor Back
+            'D' => self.move_cursor_by(-(param_or(params, 0, 1) as i64), 0),
+            // CNL - Cursor Next Line
+            'E' => {
+                self.move_cursor_by(0, param_or(params, 0, 1) as i64);
+                self.state.cursor_x = 0;
+            }
+            // CPL - Cursor Previous Line
+            'F' => {
+                self.move_cursor_by(0, -(param_or(params, 0, 1) as i64));
+                self.state.cursor_x = 0;
+            }
+            // CHA - Cursor Horizontal Absolute / HPA - Character Position Absolute
+            'G' | '`' => {
+                let x = param_or(params, 0, 1) as usize - 1;
+                self.move_cursor_to(x, self.state.cursor_y);
+            }
+            // VPA - Line Position Absolute
+            'd' => {
+                let y = param_or(params, 0, 1) as usize - 1;
+                self.move_cursor_to(self.state.cursor_x, y);
+            }
+            // CUP - Cursor Position / HVP - Horizontal Vertical Position
+            'H' | 'f' => {
+                let y = param_or(params, 0, 1) as usize - 1;
+                let x = param_or(params, 1, 1) as usize - 1;
+                self.move_cursor_to(x, y);
+            }
+            // ED - Erase in Display
+            'J' => self.erase_in_display(param_or(params, 0, 0)),
+            // EL - Erase in Line
+            'K' => self.erase_in_line(param_or(params, 0, 0)),
 
-                EscapeSequence::ForegroundColor(color_type) => {
-                    self.state.foreground_color = color_type
-                }
-                EscapeSequence::BackgroundColor(color_type) => {
-                    self.state.background_color = color_type
+            // CHT - Cursor Horizontal Forward Tabulation
+            'I' => self.horizontal_tab(param_or(params, 0, 1)),
+            // CBT - Cursor Backward Tabulation
+            'Z' => {
+                for _ in 0..param_or(params, 0, 1) {
+                    let column = self.state.tab_stops.previous(self.state.cursor_x);
+                    self.move_cursor_to(column.unwrap_or(0), self.state.cursor_y);
                 }
+            }
+            // SCOSC - Save Current Cursor Position
+            's' => self.save_cursor(),
+            // SCORC - Restore Saved Cursor Position
+            'u' => self.restore_cursor(),
+            // TBC - Tab Clear
+            'g' => match param_or(params, 0, 0) {
+                0 => self.state.tab_stops.clear(self.state.cursor_x),
+                3 => self.state.tab_stops.clear_all(),
+                mode => trace!("[csi_dispatch] unknown tab clear mode {mode}"),
+            },
 
-                EscapeSequence::DefaultForegroundColor => {
-                    self.state.foreground_color = ColorType::PrimaryForeground
-                }
+            _ => trace!("[csi_dispatch] not implemented params={params:?}, char={c:?}"),
+        }
+    }
 
-                EscapeSequence::DefaultBackgroundColor => {
-                    self.state.background_color = ColorType::PrimaryBackground
-                }
+    fn esc_dispatch(&mut self, intermediates: &[u8], ignore: bool, byte: u8) {
+        match (intermediates, byte) {
+            // HTS - Horizontal Tab Set
+            ([], b'H') => self.state.tab_stops.set(self.state.cursor_x),
+            // DECSC - Save Cursor
+            ([], b'7') => self.save_cursor(),
+            // DECRC - Restore Cursor
+            ([], b'8') => self.restore_cursor(),
+            // RIS - Reset to Initial State
+            ([], b'c') => self.state = State::new(self.settings.tab_width),
+            // IND - Index
+            ([], b'D') => self.index(),
+            // NEL - Next Line
+            ([], b'E') => self.new_line(),
+            // RI - Reverse Index
+            ([], b'M') => self.reverse_index(),
+            // DECDHL - Double Height Line, top and bottom halves
+            ([b'#'], b'3') => self.set_line_size(LineSize::DoubleHeightTop),
+            ([b'#'], b'4') => self.set_line_size(LineSize::DoubleHeightBottom),
+            // DECSWL - Single Width Line
+            ([b'#'], b'5') => self.set_line_size(LineSize::Normal),
+            // DECDWL - Double Width Line
+            ([b'#'], b'6') => self.set_line_size(LineSize::DoubleWidth),
+            // SCS - Designate G0 / G1 Character Set
+            ([slot @ (b'(' | b')')], byte) => match Charset::from_final_byte(byte) {
+                Some(charset) => self.state.charsets[(*slot - b'(') as usize] = charset,
+                None => trace!("[esc_dispatch] unsupported character set {byte:02x}"),
+            },
 
-                EscapeSequence::BlackLetterFont
-                | EscapeSequence::Faint
-                | EscapeSequence::SlowBlink
-                | EscapeSequence::NotBlinking
-                | EscapeSequence::ReverseVideo
-                | EscapeSequence::Conceal
-                | EscapeSequence::CrossedOut
-                | EscapeSequence::PrimaryFont
-                | EscapeSequence::SetAlternativeFont
-                | EscapeSequence::DisableProportionalSpacing
-                | EscapeSequence::NeitherSuperscriptNorSubscript
-                | EscapeSequence::NotReserved
-                | EscapeSequence::NormalIntensity
-                | EscapeSequence::RapidBlink => {
-                    warn!("not implemented for action: {action:?}")
-                }
-                EscapeSequence::Unimplemented(value) => {
-                    warn!("not implemented for value: {value:?}")
-                }
-                EscapeSequence::Ignore => trace!("ignored sequence"),
-            }
+            _ => trace!(
+                "[esc_dispatch] not implemented intermediates={intermediates:?}, ignore={ignore:?}, byte={byte:02x}"
+            ),
         }
     }
-
-    fn esc_dispatch(&mut self, _intermediates: &[u8], _ignore: bool, _byte: u8) {}
 }
 
 impl<'a> From<Printer<'a>> for RgbaImage {
     fn from(printer: Printer) -> Self {
-        let width = printer
-            .state
-            .text
-            .keys()
-            .map(|(x, _)| x)
-            .max()
-            .unwrap_or(&0)
-            + printer.settings_internal.glyph_advance_width as u32;
-
-        let height = printer
-            .state
-            .text
-            .keys()
-            .map(|(_, y)| y)
-            .max()
-            .unwrap_or(&0)
-            + printer.settings_internal.new_line_distance;
+        let advance_width = printer.settings_internal.glyph_advance_width;
+        let line_height = printer.settings_internal.new_line_distance;
+        let cell_left = |column: usize| column as f32 * advance_width;
+        let cell_x = |column: usize| cell_left(column).round() as u32;
+        let cell_y = |row: usize| row as u32 * line_height;
+
+        let grid = &printer.state.grid;
+        let (width, height) = printer.image_size();
+        let (width, height) = (width as u32, height as u32);
 
         let mut image = RgbaImage::new(width, height);
 
@@ -265,58 +944,413 @@ impl<'a> From<Printer<'a>> for RgbaImage {
             );
         }
 
-        // Render background before foreground from bottom to top to make it look better
-        printer.state.text.iter().rev().for_each(|((x, y), entry)| {
-            let background_end_x = x + printer.settings_internal.glyph_advance_width as u32;
-            let background_end_y = y + printer.settings.font_height as u32;
-
-            for x in *x..background_end_x {
-                for y in *y..background_end_y {
-                    let pixel =
-                        image::Rgba(printer.settings.palette.get_color(entry.background_color));
+        // Render background before foreground so glyphs overhanging their cell are not covered
+        for (row_index, row) in grid.rows.iter().enumerate() {
+            let width_factor = row.size.width_factor();
+            for (column, cell) in row.cells.iter().enumerate() {
+                let background_start_x = cell_x(column * width_factor);
+                let background_end_x = cell_x((column + 1) * width_factor);
+                let background_start_y = cell_y(row_index);
+                let background_end_y = cell_y(row_index + 1);
 
-                    image.put_pixel(x, y, pixel);
+                let (_, pixel) = printer.colors(&cell.attributes);
+                for x in background_start_x..background_end_x.min(width) {
+                    for y in background_start_y..background_end_y.min(height) {
+                        image.put_pixel(x, y, pixel);
+                    }
                 }
             }
-        });
+        }
 
-        printer.state.text.iter().for_each(|((x, y), entry)| {
-            let font = match entry.font {
-                FontState::Normal => &printer.settings.font,
-                FontState::Bold => &printer.settings.font_bold,
-                FontState::Italic => &printer.settings.font_italic,
-                FontState::ItalicBold => &printer.settings.font_italic_bold,
+        let mut shaper = Shaper::default();
+        for (row_index, row) in grid.rows.iter().enumerate() {
+            let line = LineGeometry::new(row.size, cell_y(row_index) as f32, line_height as f32);
+            let width_factor = row.size.width_factor();
+            let shaped = if printer.settings.ligatures {
+                shaper.shape_row(&row.cells, |cell| printer.shaping_font(cell))
+            } else {
+                Vec::new()
             };
+            for (column, cell) in row.cells.iter().enumerate() {
+                let attributes = &cell.attributes;
+                if cell.character == ' ' || attributes.conceal {
+                    continue;
+                }
+                // Wide characters and VS16 sequences use the emoji presentation when a
+                // font has a color glyph for them
+                let emoji_presentation =
+                    cell.width == 2 || cell.combining.contains(&VARIATION_SELECTOR_16);
+                let font = emoji_presentation
+                    .then(|| printer.color_font_for(attributes.font, cell.character))
+                    .flatten()
+                    .unwrap_or_else(|| printer.font_for(attributes.font, cell.character));
 
-            draw_text_mut(
-                &mut image,
-                Rgba(printer.settings.palette.get_color(entry.foreground_color)),
-                (*x).try_into().unwrap(),
-                (*y).try_into().unwrap(),
-                printer.settings.scale,
-                font,
-                &entry.character.to_string(),
-            );
+                let (foreground, _) = printer.colors(attributes);
+                let script = printer.script_metrics(attributes.vertical_position);
+                let scale = InternalScale {
+                    x: printer.settings.scale.x * script.scale * width_factor as f32,
+                    y: printer.settings.scale.y * script.scale * row.size.height_factor(),
+                };
+                let baseline = line.y(printer.settings_internal.baseline - script.offset);
+                let left = cell_x(column * width_factor) as f32;
+                // Glyphs keep the subpixel position of their cell, letter spacing is
+                // split evenly on both sides of them
+                let origin = (
+                    cell_left(column * width_factor)
+                        + printer.settings.letter_spacing * width_factor as f32 / 2.0,
+                    baseline,
+                );
 
-            if entry.underline {
-                // let underline_start = *x;
-                // let underline_end = x + printer.settings_internal.glyph_advance_width as u32;
-                // let underline_y = (y - 6) + printer.settings.font_height as u32;
+                if let Some(glyph) = printer.bitmap_glyph(cell.character) {
+                    let pixel = printer.settings_internal.bitmap_pixel;
+                    let left = origin.0.round();
+                    // Glyph rows count from the top of the font's cell, which sits
+                    // the font's ascent above the baseline
+                    let top = printer.settings_internal.baseline
+                        - printer.settings_internal.bitmap_ascent;
+                    let bold = matches!(attributes.font, FontState::Bold | FontState::ItalicBold);
+                    // Bold is drawn twice, one font pixel apart, like on a console
+                    for shift in 0..1 + bold as usize {
+                        let left = left + (shift * width_factor) as f32 * pixel;
+                        let pixel = (pixel * width_factor as f32, pixel);
+                        draw_bitmap_glyph(&mut image, glyph, (left, top), &line, pixel, foreground);
+                    }
+                    continue;
+                }
 
-                // for underline_x in underline_start..underline_end {
-                //     let pixel =
-                //         image::Rgb(printer.settings.palette.get_color(entry.foreground_color));
+                if let Some(Some(shaped)) = shaped.get(column) {
+                    let scaled = shaped.font.as_scaled(scale);
+                    for glyph in &shaped.glyphs {
+                        let position = (
+                            origin.0 + glyph.x * scaled.h_scale_factor(),
+                            origin.1 - glyph.y * scaled.v_scale_factor(),
+                        );
+                        let font = shaped.font;
+                        let synthesis = printer.synthesis(attributes.font, font);
+                        draw_glyph(
+                            &mut image, font, synthesis, scale, glyph.id, position, &line.clip,
+                            foreground,
+                        );
+                    }
+                    continue;
+                }
 
-                //     image.put_pixel(underline_x, underline_y - 1, pixel);
-                //     image.put_pixel(underline_x, underline_y, pixel);
-                // }
+                let area = GlyphBox {
+                    origin: (left, baseline),
+                    top: line.y(0.0),
+                    width: advance_width * (cell.width.max(1) * width_factor) as f32,
+                    height: line_height as f32 * row.size.height_factor(),
+                };
+                let cell_rect = CellRect {
+                    left,
+                    top: area.top,
+                    right: cell_x((column + cell.width.max(1)) * width_factor) as f32,
+                    bottom: area.top + area.height,
+                };
+                let light =
+                    printer.settings_internal.underline_thickness * row.size.height_factor();
+                if draw_box_character(
+                    &mut image,
+                    cell.character,
+                    &cell_rect,
+                    &line.clip,
+                    light,
+                    foreground,
+                ) {
+                    continue;
+                }
+                if let Some(glyph) =
+                    render_color_glyph(font, cell.character, scale, &area, foreground)
+                {
+                    draw_color_glyph(&mut image, &glyph, &line.clip);
+                    continue;
+                }
+
+                draw_glyph(
+                    &mut image,
+                    font,
+                    printer.synthesis(attributes.font, font),
+                    scale,
+                    font.glyph_id(cell.character),
+                    origin,
+                    &line.clip,
+                    foreground,
+                );
+                // Marks are positioned by the font relative to the origin of the base glyph,
+                // joiners and selectors without a glyph of their own are skipped
+                for &mark in &cell.combining {
+                    let font = match font.glyph_id(mark).0 {
+                        0 => printer.font_for(attributes.font, mark),
+                        _ => font,
+                    };
+                    if font.glyph_id(mark).0 != 0 {
+                        let mark = font.glyph_id(mark);
+                        let synthesis = printer.synthesis(attributes.font, font);
+                        draw_glyph(
+                            &mut image, font, synthesis, scale, mark, origin, &line.clip,
+                            foreground,
+                        );
+                    }
+                }
             }
-        });
+        }
+
+        for (row_index, row) in grid.rows.iter().enumerate() {
+            let line = LineGeometry::new(row.size, cell_y(row_index) as f32, line_height as f32);
+            let width_factor = row.size.width_factor();
+            let height_factor = row.size.height_factor();
+            for (column, cell) in row.cells.iter().enumerate() {
+                let attributes = &cell.attributes;
+                // Concealed text hides its decorations too
+                if attributes.conceal {
+                    continue;
+                }
+                let (foreground, _) = printer.colors(attributes);
+                let x_range = (
+                    cell_x(column * width_factor),
+                    cell_x((column + 1) * width_factor),
+                );
+                let cell_top = line.y(0.0);
+                let cell_bottom = line.y(line_height as f32);
+                let baseline = line.y(printer.settings_internal.baseline);
+
+                if attributes.underline != UnderlineStyle::None {
+                    let color = match attributes.underline_color {
+                        Some(color) => Rgba(printer.settings.palette.get_color(color)),
+                        None => foreground,
+                    };
+                    let thickness = printer.settings_internal.underline_thickness * height_factor;
+                    let top = (baseline
+                        + printer.settings_internal.underline_position * height_factor)
+                        .min(cell_bottom - thickness);
+
+                    draw_underline(
+                        &mut image,
+                        attributes.underline,
+                        x_range,
+                        (top, cell_bottom),
+                        &line.clip,
+                        thickness,
+                        advance_width * width_factor as f32,
+                        color,
+                    );
+                }
+
+                if attributes.overline {
+                    let bottom =
+                        cell_top + printer.settings_internal.underline_thickness * height_factor;
+                    for x in x_range.0..x_range.1 {
+                        fill_vertical_span(&mut image, x, cell_top..bottom, &line.clip, foreground);
+                    }
+                }
+
+                if attributes.crossed_out {
+                    let top =
+                        baseline - printer.settings_internal.strikeout_position * height_factor;
+                    let bottom =
+                        top + printer.settings_internal.strikeout_thickness * height_factor;
+                    for x in x_range.0..x_range.1 {
+                        fill_vertical_span(&mut image, x, top..bottom, &line.clip, foreground);
+                    }
+                }
+            }
+        }
 
         image
     }
 }
 
+/// Vertical placement of a row's content. Double height rows are drawn as
+/// one line of twice the height, starting at the top row of the pair and
+/// clipped to the half the row shows.
+pub(super) struct LineGeometry {
+    /// Top of the (possibly double height) line, in pixels.
+    pub(super) top: f32,
+    pub(super) height_factor: f32,
+    /// Pixel rows the content may be drawn on.
+    pub(super) clip: Range<f32>,
+}
+
+impl LineGeometry {
+    pub(super) fn new(size: LineSize, row_top: f32, row_height: f32) -> Self {
+        let (top, clip) = match size {
+            LineSize::Normal | LineSize::DoubleWidth => (row_top, f32::MIN..f32::MAX),
+            LineSize::DoubleHeightTop => (row_top, row_top..row_top + row_height),
+            LineSize::DoubleHeightBottom => (row_top - row_height, row_top..row_top + row_height),
+        };
+        Self {
+            top,
+            height_factor: size.height_factor(),
+            clip,
+        }
+    }
+
+    /// Pixel position of `offset` below the top of a normal sized cell.
+    pub(super) fn y(&self, offset: f32) -> f32 {
+        self.top + offset * self.height_factor
+    }
+}
+
+/// Draws a bitmap glyph with every font pixel filled as a `pixel` sized
+/// block, `corner` being the top left corner of the font's cell, its top as
+/// an offset in the line. Nothing is antialiased.
+fn draw_bitmap_glyph(
+    image: &mut RgbaImage,
+    glyph: &BitmapGlyph,
+    (left, top): (f32, f32),
+    line: &LineGeometry,
+    pixel: (f32, f32),
+    color: Rgba<u8>,
+) {
+    for (x, y) in glyph.set_pixels() {
+        let (x, y) = (glyph.left + x, glyph.top + y);
+        let columns = (left + x as f32 * pixel.0) as i64..(left + (x + 1) as f32 * pixel.0) as i64;
+        let start = line.y(top + y as f32 * pixel.1).round();
+        let end = line.y(top + (y + 1) as f32 * pixel.1).round();
+        for row in start as i64..end as i64 {
+            if !line.clip.contains(&(row as f32)) {
+                continue;
+            }
+            for column in columns.clone() {
+                blend_pixel(image, column, row, color, 1.0);
+            }
+        }
+    }
+}
+
+/// Draws the glyph `id` with its origin (left end of the baseline) at
+/// `origin`, skipping the pixel rows outside of `clip`.
+#[allow(clippy::too_many_arguments)]
+fn draw_glyph(
+    image: &mut RgbaImage,
+    font: &FontFace,
+    synthesis: Synthesis,
+    scale: InternalScale,
+    id: GlyphId,
+    origin: (f32, f32),
+    clip: &Range<f32>,
+    color: Rgba<u8>,
+) {
+    // Only the baseline is snapped to the pixel grid, horizontal positions
+    // keep their fraction so spacing stays even along the line
+    let origin = (origin.0, origin.1.round());
+    if synthesis != Synthesis::default() {
+        draw_synthetic_glyph(font, scale, id, origin, synthesis, |x, y, coverage| {
+            if clip.contains(&(y as f32)) {
+                blend_pixel(image, x, y, color, coverage);
+            }
+        });
+        return;
+    }
+    let glyph = id.with_scale_and_position(scale, point(origin.0, origin.1));
+    let Some(outline) = font.outline_glyph(glyph) else {
+        return;
+    };
+    let bounds = outline.px_bounds();
+    outline.draw(|x, y, coverage| {
+        let x = bounds.min.x as i64 + x as i64;
+        let y = bounds.min.y as i64 + y as i64;
+        if clip.contains(&(y as f32)) {
+            blend_pixel(image, x, y, color, coverage);
+        }
+    });
+}
+
+/// Draws a color glyph over the image, skipping the pixel rows outside of
+/// `clip`.
+fn draw_color_glyph(image: &mut RgbaImage, glyph: &ColorGlyph, clip: &Range<f32>) {
+    for (x, y, pixel) in glyph.image.enumerate_pixels() {
+        let (x, y) = (glyph.left + x as i64, glyph.top + y as i64);
+        if clip.contains(&(y as f32)) {
+            let Rgba([red, green, blue, alpha]) = *pixel;
+            let coverage = alpha as f32 / 255.0;
+            blend_pixel(image, x, y, Rgba([red, green, blue, 255]), coverage);
+        }
+    }
+}
+
+/// Blends `color` over the pixel at `(x, y)` with the given coverage.
+pub(super) fn blend_pixel(image: &mut RgbaImage, x: i64, y: i64, color: Rgba<u8>, coverage: f32) {
+    if x < 0 || x >= image.width() as i64 || y < 0 || y >= image.height() as i64 || coverage <= 0.0
+    {
+        return;
+    }
+    let coverage = coverage.min(1.0);
+    let pixel = image.get_pixel_mut(x as u32, y as u32);
+    *pixel = weighted_sum(*pixel, color, 1.0 - coverage, coverage);
+}
+
+/// Fills the vertical span `span` of column `x` within `clip`, antialiasing
+/// the partially covered pixels at both ends.
+fn fill_vertical_span(
+    image: &mut RgbaImage,
+    x: u32,
+    span: Range<f32>,
+    clip: &Range<f32>,
+    color: Rgba<u8>,
+) {
+    let top = span.start.max(clip.start);
+    let bottom = span.end.min(clip.end);
+    for y in top.floor() as i64..bottom.ceil() as i64 {
+        let coverage = (bottom.min(y as f32 + 1.0) - top.max(y as f32)).max(0.0);
+        blend_pixel(image, x as i64, y, color, coverage);
+    }
+}
+
+/// Draws an underline of `style` below the cell spanning `x_range`, `y_range`
+/// holds the top of the (first) line and the bottom of the cell. Pixel rows
+/// outside of `clip` are left untouched.
+#[allow(clippy::too_many_arguments)]
+fn draw_underline(
+    image: &mut RgbaImage,
+    style: UnderlineStyle,
+    x_range: (u32, u32),
+    y_range: (f32, f32),
+    clip: &Range<f32>,
+    thickness: f32,
+    cell_width: f32,
+    color: Rgba<u8>,
+) {
+    let (top, cell_bottom) = y_range;
+    for x in x_range.0..x_range.1 {
+        match style {
+            UnderlineStyle::None => {}
+            UnderlineStyle::Single => {
+                fill_vertical_span(image, x, top..top + thickness, clip, color);
+            }
+            UnderlineStyle::Double => {
+                let top = top.min(cell_bottom - thickness * 3.0);
+                fill_vertical_span(image, x, top..top + thickness, clip, color);
+                let second = top + thickness * 2.0..top + thickness * 3.0;
+                fill_vertical_span(image, x, second, clip, color);
+            }
+            UnderlineStyle::Curly => {
+                let amplitude = thickness.max(1.5);
+                let top = top.min(cell_bottom - thickness - amplitude * 2.0);
+                let phase = x as f32 / cell_width * std::f32::consts::TAU;
+                let center = top + amplitude * (1.0 - phase.cos());
+                // Thicken the span where the wave is steep so it stays continuous
+                let slope = amplitude * phase.sin() * std::f32::consts::TAU / cell_width;
+                let half = thickness / 2.0 * (1.0 + slope * slope).sqrt();
+                let span = center - half + thickness / 2.0..center + half + thickness / 2.0;
+                fill_vertical_span(image, x, span, clip, color);
+            }
+            UnderlineStyle::Dotted => {
+                let dot = thickness.round().max(1.0) as u32;
+                if (x / dot).is_multiple_of(2) {
+                    fill_vertical_span(image, x, top..top + thickness, clip, color);
+                }
+            }
+            UnderlineStyle::Dashed => {
+                let period = (cell_width / 2.0).max(2.0);
+                if (x as f32 % period) < period * 0.6 {
+                    fill_vertical_span(image, x, top..top + thickness, clip, color);
+                }
+            }
+        }
+    }
+}
+
 impl std::ops::AddAssign for FontState {
     fn add_assign(&mut self, other: Self) {
         let new_self = match (&self, other) {
@@ -372,3 +1406,264 @@ impl std::ops::SubAssign for FontState {
         *self = new_self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use vte::Parser;
+
+    use super::*;
+    use crate::ansi_to_image::{color::Color, FontFamily};
+
+    /// A printer for the default family, `columns` wide when set.
+    fn printer(columns: Option<usize>) -> Printer<'static> {
+        let family = FontFamily::default();
+        let mut printer = new(Settings {
+            font: family.regular,
+            font_bold: family.bold,
+            font_italic: family.italic,
+            font_italic_bold: family.bold_italic,
+            fallback_fonts: family.fallbacks,
+            font_height: 16.0,
+            scale: InternalScale { x: 16.0, y: 16.0 },
+            line_height: 1.0,
+            letter_spacing: 0.0,
+            palette: Palette::Vscode,
+            png_width: None,
+            tab_width: 8,
+            ambiguous_width: AmbiguousWidth::Narrow,
+            ligatures: false,
+            embed_fonts: false,
+            bitmap_font: None,
+        });
+        printer.settings_internal.columns = columns;
+        printer
+    }
+
+    fn run(columns: Option<usize>, input: &str) -> Printer<'static> {
+        let mut printer = printer(columns);
+        Parser::new().advance(&mut printer, input.as_bytes());
+        printer
+    }
+
+    /// The text of each row, with trailing blanks removed.
+    fn text(printer: &Printer) -> Vec<String> {
+        printer
+            .grid()
+            .rows
+            .iter()
+            .map(|row| {
+                row.cells
+                    .iter()
+                    .filter(|cell| cell.width > 0)
+                    .flat_map(|cell| std::iter::once(cell.character).chain(cell.combining.clone()))
+                    .collect::<String>()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    fn cell<'p>(printer: &'p Printer, column: usize, row: usize) -> &'p Cell {
+        &printer.grid().rows[row].cells[column]
+    }
+
+    fn cursor(printer: &Printer) -> (usize, usize) {
+        (printer.state.cursor_x, printer.state.cursor_y)
+    }
+
+    #[test]
+    fn relative_cursor_movement() {
+        let printer = run(None, "\x1b[3;3HX\x1b[2AU\x1b[4BD\x1b[3CF\x1b[7DB");
+        assert_eq!(text(&printer), ["   U", "", "  X", "", "  B D   F"]);
+    }
+
+    #[test]
+    fn absolute_cursor_movement() {
+        let printer = run(None, "abc\x1b[6GX\x1b[2;2HY\x1b[GZ");
+        assert_eq!(text(&printer), ["abc  X", "ZY"]);
+        // Missing and zero parameters count as one
+        let printer = run(None, "\x1b[0;0HA\x1b[0CB");
+        assert_eq!(text(&printer), ["A B"]);
+    }
+
+    #[test]
+    fn cursor_stops_at_the_edges() {
+        let printer = run(Some(5), "ab\x1b[9DX\x1b[9AY\x1b[9CZ");
+        assert_eq!(text(&printer), ["XY  Z"]);
+        let printer = run(None, "\x1b[65535;65535H");
+        assert_eq!(cursor(&printer), (MAX_COLUMNS - 1, MAX_ROWS - 1));
+    }
+
+    #[test]
+    fn erase_in_line() {
+        assert_eq!(text(&run(None, "abcdef\x1b[3G\x1b[K")), ["ab"]);
+        assert_eq!(text(&run(None, "abcdef\x1b[3G\x1b[1K")), ["   def"]);
+        assert_eq!(text(&run(None, "abcdef\x1b[3G\x1b[2K")), [""]);
+    }
+
+    #[test]
+    fn erase_fills_the_line_with_the_background() {
+        let red = ColorType::Normal(Color::Red);
+        let printer = run(Some(10), "hi\x1b[41m\x1b[K");
+        let cells = &printer.grid().rows[0].cells;
+        assert_eq!(cells.len(), 10);
+        assert_eq!(
+            cell(&printer, 1, 0).attributes.background,
+            ColorType::PrimaryBackground
+        );
+        assert!(cells[2..]
+            .iter()
+            .all(|cell| cell.attributes.background == red));
+        // Without a width only the written cells are erased
+        let printer = run(None, "hi\x1b[1G\x1b[41m\x1b[K");
+        assert_eq!(printer.grid().rows[0].cells.len(), 2);
+    }
+
+    #[test]
+    fn erase_in_display() {
+        let input = "abc\r\ndef\r\nghi\x1b[2;2H";
+        assert_eq!(
+            text(&run(None, &format!("{input}\x1b[J"))),
+            ["abc", "d", ""]
+        );
+        assert_eq!(
+            text(&run(None, &format!("{input}\x1b[1J"))),
+            ["", "  f", "ghi"]
+        );
+        assert_eq!(text(&run(None, &format!("{input}\x1b[2J"))), ["", "", ""]);
+        // There is no scrollback for ED 3 to clear
+        assert_eq!(
+            text(&run(None, &format!("{input}\x1b[3J"))),
+            ["abc", "def", "ghi"]
+        );
+    }
+
+    #[test]
+    fn wrap_waits_for_the_next_character() {
+        let printer = run(Some(3), "abc");
+        assert_eq!(cursor(&printer), (2, 0));
+        assert!(printer.state.wrap_pending);
+        assert_eq!(text(&run(Some(3), "abcd")), ["abc", "d"]);
+        assert_eq!(text(&run(Some(3), "abc\rX")), ["Xbc"]);
+        assert_eq!(text(&run(Some(3), "abc\r\nX")), ["abc", "X"]);
+    }
+
+    #[test]
+    fn line_feeds_past_the_last_row_scroll() {
+        let input = format!("first{}last", "\n".repeat(MAX_ROWS));
+        let printer = run(None, &input);
+        let text = text(&printer);
+        assert_eq!(text.len(), MAX_ROWS);
+        assert_eq!(text[0], "");
+        assert_eq!(text[MAX_ROWS - 1], "last");
+    }
+
+    #[test]
+    fn tabs() {
+        assert_eq!(text(&run(None, "a\tb\tc")), ["a       b       c"]);
+        assert_eq!(text(&run(Some(10), "a\t\t\tb")), ["a        b"]);
+    }
+
+    #[test]
+    fn backspace_overstrike() {
+        let printer = run(None, "_\x08X");
+        assert_eq!(text(&printer), ["X"]);
+        assert_eq!(
+            cell(&printer, 0, 0).attributes.underline,
+            UnderlineStyle::Single
+        );
+        let printer = run(None, "X\x08X");
+        assert_eq!(cell(&printer, 0, 0).attributes.font, FontState::Bold);
+        let printer = run(None, "a\x08b");
+        assert_eq!(text(&printer), ["b"]);
+        assert_eq!(cell(&printer, 0, 0).attributes, Attributes::default());
+    }
+
+    #[test]
+    fn save_and_restore_cursor() {
+        let printer = run(None, "ab\x1b7\x1b[31mcd\r\n\x1b8e");
+        assert_eq!(text(&printer), ["abed"]);
+        assert_eq!(
+            cell(&printer, 2, 0).attributes.foreground,
+            ColorType::PrimaryForeground
+        );
+        assert_eq!(
+            cell(&printer, 3, 0).attributes.foreground,
+            ColorType::Normal(Color::Red)
+        );
+        // Restoring without a saved cursor moves home
+        assert_eq!(text(&run(None, "ab\r\ncd\x1b8X")), ["Xb", "cd"]);
+    }
+
+    #[test]
+    fn full_reset() {
+        let printer = run(None, "abc\r\n\x1b[31m\x1b(0\x1bcq");
+        assert_eq!(text(&printer), ["q"]);
+        assert_eq!(cell(&printer, 0, 0).attributes, Attributes::default());
+    }
+
+    #[test]
+    fn index_and_reverse_index() {
+        assert_eq!(text(&run(None, "ab\x1bDc")), ["ab", "  c"]);
+        assert_eq!(text(&run(None, "ab\x1bEc")), ["ab", "c"]);
+        assert_eq!(text(&run(None, "a\r\nb\x1bMc")), ["ac", "b"]);
+        // On the first row the rows below scroll down
+        assert_eq!(text(&run(None, "a\x1bMb")), [" b", "a"]);
+    }
+
+    #[test]
+    fn character_sets() {
+        assert_eq!(text(&run(None, "\x1b(0qx\x1b(Bq")), ["─│q"]);
+        assert_eq!(text(&run(None, "\x1b)0q\x0eq\x0fq")), ["q─q"]);
+        // The shifted charset is saved with the cursor
+        assert_eq!(text(&run(None, "\x1b)0\x0e\x1b7\x0f\x1b8q")), ["─"]);
+    }
+
+    #[test]
+    fn wide_characters() {
+        let printer = run(None, "a漢b");
+        assert_eq!(text(&printer), ["a漢b"]);
+        assert_eq!(cell(&printer, 1, 0).width, 2);
+        assert_eq!(cell(&printer, 2, 0).width, 0);
+        assert_eq!(cell(&printer, 3, 0).character, 'b');
+        // Overwriting either half blanks the other one
+        assert_eq!(text(&run(None, "漢\x1b[2GX")), [" X"]);
+        assert_eq!(text(&run(None, "漢\x1b[1GX")), ["X"]);
+    }
+
+    #[test]
+    fn wide_characters_wrap_before_the_last_column() {
+        let printer = run(Some(3), "ab漢c");
+        assert_eq!(text(&printer), ["ab", "漢c"]);
+        assert_eq!(cell(&printer, 0, 1).width, 2);
+    }
+
+    #[test]
+    fn combining_marks() {
+        let printer = run(None, "e\u{301}x");
+        assert_eq!(text(&printer), ["e\u{301}x"]);
+        assert_eq!(cell(&printer, 0, 0).combining, ['\u{301}']);
+        assert_eq!(cell(&printer, 1, 0).character, 'x');
+        // A mark at the start of a line gets its own cell
+        assert_eq!(cell(&run(None, "\u{301}"), 0, 0).character, '\u{301}');
+    }
+
+    #[test]
+    fn grapheme_clusters_take_one_cell() {
+        let cases = [
+            ("👩\u{200d}💻x", vec![ZERO_WIDTH_JOINER, '💻']),
+            ("👍\u{1f3fd}x", vec!['\u{1f3fd}']),
+            ("🇫🇷x", vec!['🇷']),
+            ("❤\u{fe0f}x", vec![VARIATION_SELECTOR_16]),
+        ];
+        for (input, combining) in cases {
+            let printer = run(None, input);
+            assert_eq!(cell(&printer, 0, 0).combining, combining, "{input}");
+            assert_eq!(cell(&printer, 0, 0).width, 2, "{input}");
+            assert_eq!(cell(&printer, 2, 0).character, 'x', "{input}");
+        }
+        // Only pairs of regional indicators make a flag
+        let printer = run(None, "🇫🇷🇩");
+        assert_eq!(cell(&printer, 2, 0).character, '🇩');
+    }
+}
@@ -0,0 +1,113 @@
+//! OpenType shaping of runs of cells, used to draw ligatures and complex
+//! scripts.
+
+use std::collections::HashMap;
+
+use ab_glyph::GlyphId;
+use rustybuzz::{Face, UnicodeBuffer};
+
+use super::{font_cache::FontFace, grid::Cell};
+
+/// A glyph produced by shaping, its position is in font units relative to
+/// the origin of the cell its cluster starts in.
+pub(super) struct ShapedGlyph {
+    pub(super) id: GlyphId,
+    pub(super) x: f32,
+    pub(super) y: f32,
+}
+
+/// The glyphs drawn for a cell and the font they come from.
+pub(super) struct ShapedCell<'f> {
+    pub(super) font: &'f FontFace<'f>,
+    pub(super) glyphs: Vec<ShapedGlyph>,
+}
+
+/// Shapes rows, keeping the parsed faces of the fonts it has seen.
+#[derive(Default)]
+pub(super) struct Shaper<'f> {
+    /// Faces by the address of their font, faces of one file share its data.
+    faces: HashMap<usize, Option<Face<'f>>>,
+}
+
+impl<'f> Shaper<'f> {
+    /// Shapes `cells` in runs of cells with the same attributes and font.
+    /// Glyphs are kept on the cell grid: each glyph is drawn from the cell its
+    /// cluster starts in, so a ligature covers the cells of its characters.
+    /// Cells `font_for` returns `None` for are left out of the runs.
+    pub(super) fn shape_row(
+        &mut self,
+        cells: &[Cell],
+        font_for: impl Fn(&Cell) -> Option<&'f FontFace<'f>>,
+    ) -> Vec<Option<ShapedCell<'f>>> {
+        let mut shaped = Vec::with_capacity(cells.len());
+        let mut run_start = 0;
+        let mut run_font: Option<&'f FontFace<'f>> = None;
+        for (column, cell) in cells.iter().enumerate() {
+            let font = font_for(cell);
+            let continues_run = match (font, run_font) {
+                (Some(font), Some(run_font)) => {
+                    std::ptr::eq(font, run_font) && cells[column - 1].attributes == cell.attributes
+                }
+                _ => false,
+            };
+            if !continues_run {
+                if let Some(run_font) = run_font {
+                    shaped.extend(self.shape_run(run_font, &cells[run_start..column]));
+                }
+                if font.is_none() {
+                    shaped.push(None);
+                }
+                run_start = column;
+                run_font = font;
+            }
+        }
+        if let Some(run_font) = run_font {
+            shaped.extend(self.shape_run(run_font, &cells[run_start..]));
+        }
+        shaped
+    }
+
+    fn shape_run(&mut self, font: &'f FontFace<'f>, cells: &[Cell]) -> Vec<Option<ShapedCell<'f>>> {
+        let face = self
+            .faces
+            .entry(font as *const FontFace as usize)
+            .or_insert_with(|| font.face().cloned().map(Face::from_face));
+        let Some(face) = face else {
+            return cells.iter().map(|_| None).collect();
+        };
+
+        let mut text = String::new();
+        let mut starts = Vec::with_capacity(cells.len());
+        for cell in cells {
+            starts.push(text.len());
+            text.push(cell.character);
+            text.extend(&cell.combining);
+        }
+        let mut buffer = UnicodeBuffer::new();
+        buffer.push_str(&text);
+        let output = rustybuzz::shape(face, &[], buffer);
+
+        let mut shaped = cells
+            .iter()
+            .map(|_| ShapedCell {
+                font,
+                glyphs: Vec::new(),
+            })
+            .collect::<Vec<_>>();
+        // Pen position within the cluster, for clusters of several glyphs
+        let mut pen = (usize::MAX, 0.0);
+        for (info, position) in output.glyph_infos().iter().zip(output.glyph_positions()) {
+            let cell = starts.partition_point(|start| *start <= info.cluster as usize) - 1;
+            if pen.0 != cell {
+                pen = (cell, 0.0);
+            }
+            shaped[cell].glyphs.push(ShapedGlyph {
+                id: GlyphId(info.glyph_id as u16),
+                x: pen.1 + position.x_offset as f32,
+                y: position.y_offset as f32,
+            });
+            pen.1 += position.x_advance as f32;
+        }
+        shaped.into_iter().map(Some).collect()
+    }
+}
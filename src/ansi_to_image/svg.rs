@@ -0,0 +1,410 @@
+//! SVG output. Text is written as `<text>` runs over the cell backgrounds
+//! and decorations, so it stays crisp when zoomed and can be selected.
+
+use std::{fmt::Write, ops::Range};
+
+use ab_glyph::{Font, ScaleFont};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use image::Rgba;
+
+use super::{
+    color::ColorType,
+    font_cache::FontFace,
+    grid::{Cell, LineSize, UnderlineStyle},
+    markup::escape_cells,
+    printer::{FontState, LineGeometry, Printer},
+    system_fonts::family_name,
+};
+
+/// Family the embedded faces are declared as.
+const FONT_FAMILY: &str = "nu-image";
+/// Control point distance, relative to the segment width, of the cubic curve
+/// closest to half a period of a cosine.
+const HALF_WAVE_CONTROL: f32 = 0.3642;
+
+/// Renders the printer's grid with the same layout as the rasterised image.
+pub(super) fn render_svg(printer: &Printer) -> String {
+    let metrics = printer.metrics();
+    let advance_width = metrics.glyph_advance_width;
+    let line_height = metrics.new_line_distance as f32;
+    let cell_x = |column: usize| (column as f32 * advance_width).round();
+
+    let grid = printer.grid();
+    let width = cell_x(grid.columns().max(1));
+    let height = line_height * grid.rows.len().max(1) as f32;
+    let palette = &printer.settings().palette;
+    let primary_background = Rgba(palette.get_color(ColorType::PrimaryBackground));
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+    );
+    let _ = writeln!(svg, "<style>");
+    svg.push_str(&font_faces(printer));
+    let _ = writeln!(
+        svg,
+        r#"text {{ font-family: {}; font-size: {}px; white-space: pre; }}"#,
+        font_families(printer),
+        font_size(&printer.settings().font, printer)
+    );
+    let _ = writeln!(svg, "</style>");
+    let _ = writeln!(
+        svg,
+        r#"<rect width="100%" height="100%"{}/>"#,
+        paint("fill", primary_background)
+    );
+
+    for (row_index, row) in grid.rows.iter().enumerate() {
+        let top = row_index as f32 * line_height;
+        let width_factor = row.size.width_factor();
+        for (columns, background) in
+            runs(&row.cells, |cell| Some(printer.colors(&cell.attributes).1))
+        {
+            if background == primary_background {
+                continue;
+            }
+            let left = cell_x(columns.start * width_factor);
+            let right = cell_x(columns.end * width_factor);
+            let _ = writeln!(
+                svg,
+                r#"<rect x="{left}" y="{top}" width="{}" height="{line_height}"{}/>"#,
+                right - left,
+                paint("fill", background)
+            );
+        }
+    }
+
+    for (row_index, row) in grid.rows.iter().enumerate() {
+        let line = LineGeometry::new(row.size, row_index as f32 * line_height, line_height);
+        let mut content = String::new();
+        write_text(&mut content, printer, &row.cells);
+        write_decorations(&mut content, printer, &row.cells);
+        if content.is_empty() {
+            continue;
+        }
+
+        // Rows are drawn in the coordinates of a normal sized line, scaled
+        // to the size of the row and clipped to the half a double height
+        // row shows
+        let transform = match row.size {
+            LineSize::Normal => format!("translate(0 {})", line.top),
+            _ => format!(
+                "translate(0 {}) scale({} {})",
+                line.top,
+                row.size.width_factor(),
+                line.height_factor
+            ),
+        };
+        match row.size {
+            LineSize::DoubleHeightTop | LineSize::DoubleHeightBottom => {
+                let _ = writeln!(
+                    svg,
+                    r#"<clipPath id="row-{row_index}"><rect y="{}" width="{width}" height="{}"/></clipPath>"#,
+                    line.clip.start,
+                    line.clip.end - line.clip.start
+                );
+                let _ = writeln!(svg, r#"<g clip-path="url(#row-{row_index})">"#);
+            }
+            _ => svg.push_str("<g>\n"),
+        }
+        let _ = writeln!(svg, r#"<g transform="{transform}">"#);
+        svg.push_str(&content);
+        svg.push_str("</g>\n</g>\n");
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Writes a `<text>` element for each run of cells drawn alike. Runs are
+/// stretched to the width of their cells so they stay on the grid whatever
+/// font the viewer ends up using, wide characters (and the spacers after
+/// them) get runs of their own as their glyphs are rarely twice as wide.
+fn write_text(svg: &mut String, printer: &Printer, cells: &[Cell]) {
+    let metrics = printer.metrics();
+    let advance_width = metrics.glyph_advance_width;
+    let style = |cell: &Cell| {
+        let attributes = &cell.attributes;
+        (!attributes.conceal).then(|| {
+            (
+                printer.colors(attributes).0,
+                attributes.font,
+                attributes.vertical_position,
+                cell.width != 1,
+            )
+        })
+    };
+    for (columns, (foreground, font, position, _)) in runs(cells, style) {
+        // Blanks around the run are left out, the ones inside it are kept
+        let drawn = |column: &usize| cells[*column].character != ' ';
+        let Some(start) = columns.clone().find(drawn) else {
+            continue;
+        };
+        let end = columns
+            .clone()
+            .rfind(drawn)
+            .map_or(start, |column| column + 1);
+        let end = (end..columns.end)
+            .find(|column| cells[*column].width != 0)
+            .unwrap_or(columns.end);
+        let text = escape_cells(&cells[start..end]);
+
+        let script = printer.script_metrics(position);
+        let x = start as f32 * advance_width + printer.settings().letter_spacing / 2.0;
+        let y = metrics.baseline - script.offset;
+        let _ = write!(
+            svg,
+            r#"<text x="{x}" y="{y}" textLength="{}" lengthAdjust="spacing"{}"#,
+            (end - start) as f32 * advance_width,
+            paint("fill", foreground)
+        );
+        if matches!(font, FontState::Bold | FontState::ItalicBold) {
+            svg.push_str(r#" font-weight="bold""#);
+        }
+        if matches!(font, FontState::Italic | FontState::ItalicBold) {
+            svg.push_str(r#" font-style="italic""#);
+        }
+        if script.scale != 1.0 {
+            let size = font_size(&printer.settings().font, printer) * script.scale;
+            let _ = write!(svg, r#" font-size="{size}px""#);
+        }
+        let _ = writeln!(svg, ">{text}</text>");
+    }
+}
+
+/// Writes the underlines, overlines and strikethroughs of a row, placed like
+/// the rasteriser places them. Concealed cells have none.
+fn write_decorations(svg: &mut String, printer: &Printer, cells: &[Cell]) {
+    let metrics = printer.metrics();
+    let advance_width = metrics.glyph_advance_width;
+    let line_height = metrics.new_line_distance as f32;
+    let palette = &printer.settings().palette;
+    let span = |columns: &Range<usize>| {
+        (
+            columns.start as f32 * advance_width,
+            columns.end as f32 * advance_width,
+        )
+    };
+
+    let underline = |cell: &Cell| {
+        let attributes = &cell.attributes;
+        let color = match attributes.underline_color {
+            Some(color) => Rgba(palette.get_color(color)),
+            None => printer.colors(attributes).0,
+        };
+        (!attributes.conceal && attributes.underline != UnderlineStyle::None)
+            .then_some((attributes.underline, color))
+    };
+    for (columns, (style, color)) in runs(cells, underline) {
+        let (left, right) = span(&columns);
+        let thickness = metrics.underline_thickness;
+        let top = (metrics.baseline + metrics.underline_position).min(line_height - thickness);
+        match style {
+            UnderlineStyle::None => {}
+            UnderlineStyle::Single => write_rect(svg, (left, right), top, thickness, color),
+            UnderlineStyle::Double => {
+                let top = top.min(line_height - thickness * 3.0);
+                write_rect(svg, (left, right), top, thickness, color);
+                write_rect(svg, (left, right), top + thickness * 2.0, thickness, color);
+            }
+            UnderlineStyle::Curly => {
+                let amplitude = thickness.max(1.5);
+                let top = top.min(line_height - thickness - amplitude * 2.0);
+                let center = top + thickness / 2.0;
+                // One period per cell, starting at its crest like the raster
+                let mut path = format!("M{left} {center}");
+                let half_wave = advance_width / 2.0;
+                let halves = ((right - left) / half_wave).round() as usize;
+                for half in 0..halves {
+                    let x = left + half as f32 * half_wave;
+                    let (from, to) = match half % 2 {
+                        0 => (center, center + amplitude * 2.0),
+                        _ => (center + amplitude * 2.0, center),
+                    };
+                    let control = half_wave * HALF_WAVE_CONTROL;
+                    let _ = write!(
+                        path,
+                        " C{} {from} {} {to} {} {to}",
+                        x + control,
+                        x + half_wave - control,
+                        x + half_wave
+                    );
+                }
+                let _ = writeln!(
+                    svg,
+                    r#"<path d="{path}" fill="none" stroke-width="{thickness}"{}/>"#,
+                    paint("stroke", color)
+                );
+            }
+            UnderlineStyle::Dotted | UnderlineStyle::Dashed => {
+                let (on, off) = match style {
+                    UnderlineStyle::Dotted => {
+                        let dot = thickness.round().max(1.0);
+                        (dot, dot)
+                    }
+                    _ => {
+                        let period = (advance_width / 2.0).max(2.0);
+                        (period * 0.6, period * 0.4)
+                    }
+                };
+                // The pattern starts at the left edge of the image, not of the run
+                let offset = left % (on + off);
+                let y = top + thickness / 2.0;
+                let _ = writeln!(
+                    svg,
+                    r#"<line x1="{left}" y1="{y}" x2="{right}" y2="{y}" stroke-width="{thickness}" stroke-dasharray="{on} {off}" stroke-dashoffset="{offset}"{}/>"#,
+                    paint("stroke", color)
+                );
+            }
+        }
+    }
+
+    let overline = |cell: &Cell| {
+        let attributes = &cell.attributes;
+        (!attributes.conceal && attributes.overline).then(|| printer.colors(attributes).0)
+    };
+    for (columns, color) in runs(cells, overline) {
+        write_rect(svg, span(&columns), 0.0, metrics.underline_thickness, color);
+    }
+
+    let crossed_out = |cell: &Cell| {
+        let attributes = &cell.attributes;
+        (!attributes.conceal && attributes.crossed_out).then(|| printer.colors(attributes).0)
+    };
+    for (columns, color) in runs(cells, crossed_out) {
+        let top = metrics.baseline - metrics.strikeout_position;
+        write_rect(svg, span(&columns), top, metrics.strikeout_thickness, color);
+    }
+}
+
+fn write_rect(svg: &mut String, (left, right): (f32, f32), top: f32, height: f32, color: Rgba<u8>) {
+    let _ = writeln!(
+        svg,
+        r#"<rect x="{left}" y="{top}" width="{}" height="{height}"{}/>"#,
+        right - left,
+        paint("fill", color)
+    );
+}
+
+/// Splits `cells` into runs of consecutive cells with the same `key`, cells
+/// without a key are left out.
+fn runs<K: PartialEq>(cells: &[Cell], key: impl Fn(&Cell) -> Option<K>) -> Vec<(Range<usize>, K)> {
+    let mut runs: Vec<(Range<usize>, K)> = Vec::new();
+    for (column, cell) in cells.iter().enumerate() {
+        let Some(key) = key(cell) else {
+            continue;
+        };
+        match runs.last_mut() {
+            Some((columns, last)) if columns.end == column && *last == key => columns.end += 1,
+            _ => runs.push((column..column + 1, key)),
+        }
+    }
+    runs
+}
+
+/// The CSS font family list: the embedded faces when there are any, then the
+/// families of the font and its fallback fonts by name.
+fn font_families(printer: &Printer) -> String {
+    let settings = printer.settings();
+    let mut families = Vec::new();
+    if settings.embed_fonts {
+        families.push(FONT_FAMILY.to_string());
+    }
+    let names = std::iter::once(&settings.font)
+        .chain(&settings.fallback_fonts)
+        .filter_map(|font| family_name(&font.face()?.tables().name?))
+        // Names are written unescaped in a quoted string of a style element
+        .filter(|name| !name.contains(['"', '\\', '<', '&']));
+    for name in names {
+        if !families.contains(&name) {
+            families.push(name);
+        }
+    }
+    let mut list = String::new();
+    for family in families {
+        let _ = write!(list, r#""{family}", "#);
+    }
+    list + "monospace"
+}
+
+/// `@font-face` rules embedding the faces of the family the text uses, with
+/// `--embed-fonts` only. Faces that repeat the data of another face, like
+/// styles taken from the axes of a variable font, are left for the viewer to
+/// synthesize, and so are font collections, which browsers do not load.
+fn font_faces(printer: &Printer) -> String {
+    let settings = printer.settings();
+    if !settings.embed_fonts {
+        return String::new();
+    }
+    let faces = [
+        (FontState::Normal, Some(&settings.font), "normal", "normal"),
+        (
+            FontState::Bold,
+            settings.font_bold.as_ref(),
+            "bold",
+            "normal",
+        ),
+        (
+            FontState::Italic,
+            settings.font_italic.as_ref(),
+            "normal",
+            "italic",
+        ),
+        (
+            FontState::ItalicBold,
+            settings.font_italic_bold.as_ref(),
+            "bold",
+            "italic",
+        ),
+    ];
+    let used = |state: FontState| {
+        printer.grid().rows.iter().any(|row| {
+            row.cells.iter().any(|cell| {
+                cell.character != ' ' && !cell.attributes.conceal && cell.attributes.font == state
+            })
+        })
+    };
+    let mut embedded: Vec<&[u8]> = Vec::new();
+    let mut rules = String::new();
+    for (state, font, weight, style) in faces {
+        let Some(data) = font.filter(|_| used(state)).map(|font| font.font_data()) else {
+            continue;
+        };
+        if data.starts_with(b"ttcf") || embedded.contains(&data) {
+            continue;
+        }
+        embedded.push(data);
+        let (mime, format) = if data.starts_with(b"OTTO") {
+            ("font/otf", "opentype")
+        } else {
+            ("font/ttf", "truetype")
+        };
+        let _ = writeln!(
+            rules,
+            r#"@font-face {{ font-family: "{FONT_FAMILY}"; font-weight: {weight}; font-style: {style}; src: url("data:{mime};base64,{}") format("{format}"); }}"#,
+            STANDARD.encode(data)
+        );
+    }
+    rules
+}
+
+/// The CSS font size, the size of the em, drawing `font` at the printer's
+/// scale. The printer's scale is the height from descent to ascent.
+fn font_size(font: &FontFace, printer: &Printer) -> f32 {
+    let scaled = font.as_scaled(printer.settings().scale);
+    scaled.v_scale_factor() * font.units_per_em().unwrap_or(1000.0)
+}
+
+/// A fill or stroke attribute, with its opacity for translucent colors.
+fn paint(attribute: &str, Rgba([red, green, blue, alpha]): Rgba<u8>) -> String {
+    let mut paint = format!(r##" {attribute}="#{red:02x}{green:02x}{blue:02x}""##);
+    if alpha != u8::MAX {
+        let _ = write!(
+            paint,
+            r#" {attribute}-opacity="{:.3}""#,
+            alpha as f32 / 255.0
+        );
+    }
+    paint
+}
@@ -0,0 +1,209 @@
+//! Bold and italic synthesized from another face of the family, for
+//! families that do not ship every style.
+
+use ab_glyph::{point, Font, GlyphId, ScaleFont};
+use ab_glyph_rasterizer::Rasterizer;
+use ttf_parser::OutlineBuilder;
+
+use super::{font_cache::FontFace, internal_scale::InternalScale};
+
+/// Horizontal shift per unit of height of synthesized italics, about 12°.
+const SLANT: f32 = 0.21;
+/// Distance the edges of synthesized bold outlines move outwards, relative
+/// to the em size.
+const EMBOLDEN: f32 = 1.0 / 48.0;
+
+/// Styles a face lacks, synthesized when drawing with it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Synthesis {
+    pub(super) bold: bool,
+    pub(super) italic: bool,
+}
+
+/// Rasterises the glyph `id` emboldened and slanted as `synthesis` asks,
+/// calling `draw` with the position and coverage of each pixel. `origin` is
+/// the left end of the baseline.
+pub(super) fn draw_synthetic_glyph(
+    font: &FontFace,
+    scale: InternalScale,
+    id: GlyphId,
+    origin: (f32, f32),
+    synthesis: Synthesis,
+    mut draw: impl FnMut(i64, i64, f32),
+) {
+    let Some(face) = font.face() else {
+        return;
+    };
+    let mut builder = ContourBuilder::default();
+    if face
+        .outline_glyph(ttf_parser::GlyphId(id.0), &mut builder)
+        .is_none()
+    {
+        return;
+    }
+    builder.close();
+    let mut contours = builder.contours;
+    if synthesis.bold {
+        embolden(&mut contours, face.units_per_em() as f32 * EMBOLDEN);
+    }
+
+    let scaled = font.as_scaled(scale);
+    let (h_scale, v_scale) = (scaled.h_scale_factor(), scaled.v_scale_factor());
+    let slant = if synthesis.italic { SLANT } else { 0.0 };
+    // Font units, y up, to image pixels, y down
+    let to_pixels =
+        |(x, y): (f32, f32)| (origin.0 + (x + y * slant) * h_scale, origin.1 - y * v_scale);
+
+    let pixels = contours
+        .iter()
+        .flat_map(|contour| contour.points.iter())
+        .map(|&position| to_pixels(position));
+    let (mut min, mut max) = ((f32::MAX, f32::MAX), (f32::MIN, f32::MIN));
+    for (x, y) in pixels {
+        min = (min.0.min(x), min.1.min(y));
+        max = (max.0.max(x), max.1.max(y));
+    }
+    if min.0 > max.0 {
+        return;
+    }
+    let (left, top) = (min.0.floor(), min.1.floor());
+    let width = (max.0 - left).ceil() as usize + 1;
+    let height = (max.1 - top).ceil() as usize + 1;
+
+    let mut rasterizer = Rasterizer::new(width, height);
+    for contour in &contours {
+        let length = contour.points.len();
+        let mut start = 0;
+        for &segment in &contour.segments {
+            let at = |offset: usize| {
+                let (x, y) = to_pixels(contour.points[(start + offset) % length]);
+                point(x - left, y - top)
+            };
+            match segment {
+                1 => rasterizer.draw_line(at(0), at(1)),
+                2 => rasterizer.draw_quad(at(0), at(1), at(2)),
+                _ => rasterizer.draw_cubic(at(0), at(1), at(2), at(3)),
+            }
+            start += segment;
+        }
+    }
+    rasterizer.for_each_pixel_2d(|x, y, coverage| {
+        draw(
+            left as i64 + x as i64,
+            top as i64 + y as i64,
+            coverage.min(1.0),
+        );
+    });
+}
+
+/// A closed contour in font units. Each segment is the number of points it
+/// starts at, its end point is the first point of the next segment.
+#[derive(Default)]
+struct Contour {
+    points: Vec<(f32, f32)>,
+    segments: Vec<usize>,
+}
+
+#[derive(Default)]
+struct ContourBuilder {
+    contours: Vec<Contour>,
+    current: Option<Contour>,
+}
+
+impl OutlineBuilder for ContourBuilder {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.close();
+        self.current = Some(Contour {
+            points: vec![(x, y)],
+            segments: Vec::new(),
+        });
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        if let Some(contour) = &mut self.current {
+            contour.points.push((x, y));
+            contour.segments.push(1);
+        }
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        if let Some(contour) = &mut self.current {
+            contour.points.extend([(x1, y1), (x, y)]);
+            contour.segments.push(2);
+        }
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        if let Some(contour) = &mut self.current {
+            contour.points.extend([(x1, y1), (x2, y2), (x, y)]);
+            contour.segments.push(3);
+        }
+    }
+
+    fn close(&mut self) {
+        let Some(mut contour) = self.current.take() else {
+            return;
+        };
+        // The last segment ends where the contour starts, either by dropping
+        // the repeated start point or with a closing line
+        if contour.points.len() > 1 && contour.points.last() == contour.points.first() {
+            contour.points.pop();
+        } else {
+            contour.segments.push(1);
+        }
+        if !contour.segments.is_empty() && contour.points.len() > 1 {
+            self.contours.push(contour);
+        }
+    }
+}
+
+/// Moves every point of the outline `strength` font units outwards, along
+/// the bisector of the edges meeting at it.
+fn embolden(contours: &mut [Contour], strength: f32) {
+    // Outer contours run clockwise in TrueType outlines and counter-clockwise
+    // in CFF ones, the total area tells which side is outside
+    let area: f32 = contours
+        .iter()
+        .map(|contour| signed_area(&contour.points))
+        .sum();
+    let outward = if area < 0.0 { 1.0 } else { -1.0 };
+    let normal = |from: (f32, f32), to: (f32, f32)| {
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let length = dx.hypot(dy);
+        (length > f32::EPSILON).then(|| (-dy / length * outward, dx / length * outward))
+    };
+
+    for contour in contours {
+        let points = &contour.points;
+        let length = points.len();
+        let shifted = (0..length)
+            .map(|index| {
+                let current = points[index];
+                let previous = (1..length)
+                    .map(|offset| points[(index + length - offset) % length])
+                    .find(|other| *other != current);
+                let next = (1..length)
+                    .map(|offset| points[(index + offset) % length])
+                    .find(|other| *other != current);
+                let incoming = previous.and_then(|previous| normal(previous, current));
+                let outgoing = next.and_then(|next| normal(current, next));
+                let (Some(incoming), Some(outgoing)) = (incoming, outgoing) else {
+                    return current;
+                };
+                // Miter join, limited so sharp corners do not spike
+                let miter = (1.0 + incoming.0 * outgoing.0 + incoming.1 * outgoing.1).max(0.5);
+                (
+                    current.0 + (incoming.0 + outgoing.0) * strength / miter,
+                    current.1 + (incoming.1 + outgoing.1) * strength / miter,
+                )
+            })
+            .collect();
+        contour.points = shifted;
+    }
+}
+
+/// Shoelace area, positive for counter-clockwise contours in y up space.
+fn signed_area(points: &[(f32, f32)]) -> f32 {
+    let pairs = points.iter().zip(points.iter().cycle().skip(1));
+    pairs.map(|(a, b)| a.0 * b.1 - b.0 * a.1).sum::<f32>() / 2.0
+}
@@ -0,0 +1,258 @@
+//! Font families installed on the system, found by scanning the font
+//! directories fontconfig uses on Linux.
+
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use include_flate::lazy_static;
+use ttf_parser::{name, name_id, os2, post};
+
+use crate::debug;
+
+use super::font_cache::FaceSpec;
+
+/// Font directories are scanned this deep, which also stops symlink loops.
+const MAX_DEPTH: usize = 8;
+/// Faces at least this heavy are used for bold text.
+const BOLD_WEIGHT: u16 = 600;
+/// Width class of faces that are neither condensed nor expanded.
+const NORMAL_WIDTH: u16 = 5;
+/// Font files and font collections.
+const FONT_EXTENSIONS: [&str; 4] = ["ttf", "otf", "ttc", "otc"];
+/// Faces read from a font collection, at most.
+const MAX_COLLECTION_FACES: usize = 256;
+
+lazy_static! {
+    /// Installed faces, scanned the first time a family is looked up.
+    static ref FACES: Vec<SystemFace> = scan();
+}
+
+/// A face found in a font file.
+struct SystemFace {
+    /// Family name, normalized with [`normalize`].
+    family: String,
+    path: PathBuf,
+    /// Index of the face in a font collection.
+    index: u32,
+    italic: bool,
+    weight: u16,
+    /// Width class, from 1 (ultra condensed) to 9 (ultra expanded).
+    width: u16,
+}
+
+/// Files of the faces of an installed family.
+pub(super) struct SystemFamily {
+    pub(super) regular: FaceSpec,
+    pub(super) bold: Option<FaceSpec>,
+    pub(super) italic: Option<FaceSpec>,
+    pub(super) bold_italic: Option<FaceSpec>,
+}
+
+/// Looks up an installed family, ignoring case, spaces, dashes and
+/// underscores in its name.
+pub(super) fn find_family(name: &str) -> Option<SystemFamily> {
+    let name = normalize(name);
+    let faces: Vec<&SystemFace> = FACES.iter().filter(|face| face.family == name).collect();
+    let pick = |italic: bool, weight: u16| {
+        faces
+            .iter()
+            .filter(|face| face.italic == italic)
+            .filter(|face| (face.weight >= BOLD_WEIGHT) == (weight >= BOLD_WEIGHT))
+            .min_by_key(|face| {
+                (
+                    face.width.abs_diff(NORMAL_WIDTH),
+                    face.weight.abs_diff(weight),
+                )
+            })
+            .map(|face| face.spec())
+    };
+    // A family without an upright regular face still renders with any face
+    let regular = pick(false, 400).or_else(|| faces.first().map(|face| face.spec()))?;
+    Some(SystemFamily {
+        regular,
+        bold: pick(false, 700),
+        italic: pick(true, 400),
+        bold_italic: pick(true, 700),
+    })
+}
+
+impl SystemFace {
+    fn spec(&self) -> FaceSpec {
+        FaceSpec {
+            path: self.path.clone(),
+            index: self.index,
+            ..Default::default()
+        }
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|character| !matches!(character, ' ' | '-' | '_'))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// User directories come first, like in fontconfig's default configuration.
+fn font_directories() -> Vec<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let mut directories = Vec::new();
+    match std::env::var_os("XDG_DATA_HOME") {
+        Some(data) => directories.push(PathBuf::from(data).join("fonts")),
+        None => directories.extend(home.as_ref().map(|home| home.join(".local/share/fonts"))),
+    }
+    directories.extend(home.map(|home| home.join(".fonts")));
+    directories.push(PathBuf::from("/usr/local/share/fonts"));
+    directories.push(PathBuf::from("/usr/share/fonts"));
+    directories
+}
+
+fn scan() -> Vec<SystemFace> {
+    let mut faces = Vec::new();
+    for directory in font_directories() {
+        scan_directory(&directory, 0, &mut faces);
+    }
+    debug!("found {} system font faces", faces.len());
+    faces
+}
+
+fn scan_directory(directory: &Path, depth: usize, faces: &mut Vec<SystemFace>) {
+    if depth > MAX_DEPTH {
+        return;
+    }
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        return;
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.is_dir() {
+            scan_directory(&path, depth + 1, faces);
+            continue;
+        }
+        let is_font = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                FONT_EXTENSIONS
+                    .iter()
+                    .any(|font| extension.eq_ignore_ascii_case(font))
+            });
+        if !is_font {
+            continue;
+        }
+        for tables in read_face_tables(&path) {
+            let Some(family) =
+                name::Table::parse(&tables.name).and_then(|names| family_name(&names))
+            else {
+                continue;
+            };
+            let os2 = tables.os2.as_deref().and_then(os2::Table::parse);
+            let italic_angle = tables
+                .post
+                .as_deref()
+                .and_then(post::Table::parse)
+                .map_or(0.0, |post| post.italic_angle);
+            let style = os2.map(|os2| os2.style()).unwrap_or_default();
+            faces.push(SystemFace {
+                family: normalize(&family),
+                path: path.clone(),
+                index: tables.index,
+                italic: style != os2::Style::Normal || italic_angle != 0.0,
+                weight: os2.map_or(400, |os2| os2.weight().to_number()),
+                width: os2.map_or(NORMAL_WIDTH, |os2| os2.width().to_number()),
+            });
+        }
+    }
+}
+
+/// Tables of a face that describe its family and style.
+struct FaceTables {
+    index: u32,
+    name: Vec<u8>,
+    os2: Option<Vec<u8>>,
+    post: Option<Vec<u8>>,
+}
+
+/// Reads the `name`, `OS/2` and `post` tables of every face in the font file
+/// at `path`, leaving the rest of the file, mostly glyphs, unread.
+fn read_face_tables(path: &Path) -> Vec<FaceTables> {
+    let mut faces = Vec::new();
+    let Ok(mut file) = File::open(path) else {
+        return faces;
+    };
+    let Some(header) = read_at(&mut file, 0, 12) else {
+        return faces;
+    };
+    // Collections list the offsets of their faces' table directories
+    let offsets = if header.starts_with(b"ttcf") {
+        let count = (u32_at(&header, 8) as usize).min(MAX_COLLECTION_FACES);
+        let Some(offsets) = read_at(&mut file, 12, count * 4) else {
+            return faces;
+        };
+        offsets
+            .chunks_exact(4)
+            .map(|offset| u32_at(offset, 0))
+            .collect()
+    } else {
+        vec![0]
+    };
+    for (index, offset) in offsets.into_iter().enumerate() {
+        let Some(directory) = read_at(&mut file, offset as u64, 12) else {
+            continue;
+        };
+        let count = u16::from_be_bytes([directory[4], directory[5]]) as usize;
+        let Some(records) = read_at(&mut file, offset as u64 + 12, count * 16) else {
+            continue;
+        };
+        let mut table = |tag: &[u8; 4]| {
+            let record = records
+                .chunks_exact(16)
+                .find(|record| record[..4] == *tag)?;
+            let (offset, length) = (u32_at(record, 8), u32_at(record, 12));
+            read_at(&mut file, offset as u64, length as usize)
+        };
+        let Some(name) = table(b"name") else {
+            continue;
+        };
+        faces.push(FaceTables {
+            index: index as u32,
+            name,
+            os2: table(b"OS/2"),
+            post: table(b"post"),
+        });
+    }
+    faces
+}
+
+/// Reads `length` bytes of `file` from `offset`, `None` when the file ends
+/// before.
+fn read_at(file: &mut File, offset: u64, length: usize) -> Option<Vec<u8>> {
+    file.seek(SeekFrom::Start(offset)).ok()?;
+    let mut data = Vec::new();
+    file.take(length as u64).read_to_end(&mut data).ok()?;
+    (data.len() == length).then_some(data)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+/// The typographic family name when the font has one, which groups every
+/// weight of the family, otherwise the legacy family name.
+pub(super) fn family_name(names: &name::Table) -> Option<String> {
+    let name = |id: u16| {
+        names
+            .names
+            .into_iter()
+            .filter(|name| name.name_id == id)
+            .find_map(|name| name.to_string())
+    };
+    name(name_id::TYPOGRAPHIC_FAMILY).or_else(|| name(name_id::FAMILY))
+}
@@ -0,0 +1,120 @@
+use std::collections::BTreeSet;
+
+/// Columns between the default tab stops, as in most terminals.
+pub(super) const DEFAULT_TAB_WIDTH: usize = 8;
+
+/// Horizontal tab stops. The captured output has no fixed width, so the
+/// default stops repeat every `interval` columns indefinitely, while stops set
+/// with `HTS` or cleared with `TBC` are kept as exceptions to that pattern.
+#[derive(Debug)]
+pub(super) struct TabStops {
+    interval: Option<usize>,
+    set: BTreeSet<usize>,
+    cleared: BTreeSet<usize>,
+}
+
+impl Default for TabStops {
+    fn default() -> Self {
+        Self::new(DEFAULT_TAB_WIDTH)
+    }
+}
+
+impl TabStops {
+    pub(super) fn new(interval: usize) -> Self {
+        Self {
+            interval: Some(interval.max(1)),
+            set: BTreeSet::new(),
+            cleared: BTreeSet::new(),
+        }
+    }
+
+    fn is_stop(&self, column: usize) -> bool {
+        if self.set.contains(&column) {
+            return true;
+        }
+        match self.interval {
+            Some(interval) => column.is_multiple_of(interval) && !self.cleared.contains(&column),
+            None => false,
+        }
+    }
+
+    /// The first tab stop after `column`, if any.
+    pub(super) fn next(&self, column: usize) -> Option<usize> {
+        let custom = self.set.range(column + 1..).next().copied();
+        let default = self.interval.map(|interval| {
+            let mut stop = (column / interval + 1) * interval;
+            while self.cleared.contains(&stop) {
+                stop += interval;
+            }
+            stop
+        });
+        match (custom, default) {
+            (Some(custom), Some(default)) => Some(custom.min(default)),
+            (custom, default) => custom.or(default),
+        }
+    }
+
+    /// The last tab stop before `column`, if any.
+    pub(super) fn previous(&self, column: usize) -> Option<usize> {
+        (0..column).rev().find(|column| self.is_stop(*column))
+    }
+
+    /// HTS - Horizontal Tab Set
+    pub(super) fn set(&mut self, column: usize) {
+        self.cleared.remove(&column);
+        self.set.insert(column);
+    }
+
+    /// TBC 0 - clears the tab stop at `column`
+    pub(super) fn clear(&mut self, column: usize) {
+        self.set.remove(&column);
+        self.cleared.insert(column);
+    }
+
+    /// TBC 3 - clears every tab stop
+    pub(super) fn clear_all(&mut self) {
+        self.interval = None;
+        self.set.clear();
+        self.cleared.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_stops_repeat() {
+        let stops = TabStops::new(4);
+        assert_eq!(stops.next(0), Some(4));
+        assert_eq!(stops.next(4), Some(8));
+        assert_eq!(stops.next(1001), Some(1004));
+        assert_eq!(stops.previous(5), Some(4));
+        assert_eq!(stops.previous(4), Some(0));
+        assert_eq!(stops.previous(0), None);
+    }
+
+    #[test]
+    fn set_and_cleared_stops() {
+        let mut stops = TabStops::default();
+        stops.set(3);
+        stops.clear(8);
+        assert_eq!(stops.next(0), Some(3));
+        assert_eq!(stops.next(3), Some(16));
+        assert_eq!(stops.previous(16), Some(3));
+        stops.set(8);
+        assert_eq!(stops.next(3), Some(8));
+    }
+
+    #[test]
+    fn clear_all_drops_the_defaults() {
+        let mut stops = TabStops::new(4);
+        stops.clear_all();
+        assert_eq!(stops.next(0), None);
+        assert_eq!(stops.previous(10), None);
+        stops.set(6);
+        assert_eq!(stops.next(0), Some(6));
+        assert_eq!(stops.next(6), None);
+        assert_eq!(stops.previous(10), Some(6));
+    }
+}
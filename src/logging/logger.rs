@@ -14,7 +14,7 @@ lazy_static! {
         let decorator = TermDecorator::new().build();
         let drain = CompactFormat::new(decorator).build().fuse();
         let drain = RuntimeLevelFilter {
-            drain: drain,
+            drain,
             on: LOG_LEVEL.clone(),
         }
         .fuse();
@@ -89,23 +89,34 @@ impl SimplePluginCommand for ToPngCommand {
         .optional(
             "output-path",
             SyntaxShape::Filepath,
-            "output file path (by default uses current timestamp)",
+            "output file path (by default uses current timestamp), its extension selects the output format",
         )
                 .named("width", SyntaxShape::Int, "output width", Some('w'))
+                .named("format", SyntaxShape::String, "output format, one of png, jpeg, webp (lossless), bmp, qoi, tiff, svg or html (by default taken from the output path's extension, or png)", None)
+                .named("quality", SyntaxShape::Int, "JPEG quality from 1 to 100 (defaults to 90)", None)
+                .named("font-size", SyntaxShape::Number, "font size in pixels, up to 1000 (defaults to 50)", None)
+                .named("line-height", SyntaxShape::Number, "distance between lines as a multiple of the font size, up to 10 (defaults to 1)", None)
+                .named("letter-spacing", SyntaxShape::Number, "pixels added to the width of each cell, can be negative (defaults to 0)", None)
+                .named("tab-width", SyntaxShape::Int, "number of columns between tab stops (defaults to 8)", None)
+                .named("ambiguous-width", SyntaxShape::String, "width of East Asian ambiguous characters, `narrow` (default) or `wide`", None)
+                .switch("ligatures", "shape text with the font's OpenType features, drawing programming ligatures", None)
+                .switch("embed-fonts", "embed the font faces in SVG output instead of referring to them by name", None)
                 .named("theme",SyntaxShape::String,format!("select theme of the output, one of: {:?}\n\t\tby default uses `vscode` theme and you can mix this flag with custom theme colors every other colors will be from the selected theme",Palette::list()),Some('t'))
                 .named(
                     "font",
                     SyntaxShape::String,
                     format!(
-                        "Select the font from one of {:?}, by default the first font in the list will be used",
+                        "Select the font from one of {:?} or the name of an installed font family, by default the first font in the list will be used",
                         FontFamily::list()
                     ),
                     None,
                 )
-                .named("custom-font-regular", SyntaxShape::Filepath, "custom font Regular font path", None)
-                .named("custom-font-bold", SyntaxShape::Filepath, "custom font Bold font path", None)
-                .named("custom-font-italic", SyntaxShape::Filepath, "custom font Italic font path", None)
-                .named("custom-font-bold_italic", SyntaxShape::Filepath, "custom font Bold Italic font path", None)
+                .named("fallback-font", font_shape(), "font used for glyphs missing from the selected font, can be repeated and is searched in the given order", None)
+                .named("custom-font-regular", font_shape(), "custom font Regular font path, or a record with its `path`, face `index` and `weight`/`italic` axis values", None)
+                .named("custom-font-bold", font_shape(), "custom font Bold font path, or a record with its `path`, face `index` and `weight`/`italic` axis values", None)
+                .named("custom-font-italic", font_shape(), "custom font Italic font path, or a record with its `path`, face `index` and `weight`/`italic` axis values", None)
+                .named("custom-font-bold_italic", font_shape(), "custom font Bold Italic font path, or a record with its `path`, face `index` and `weight`/`italic` axis values", None)
+                .named("bitmap-font", SyntaxShape::Filepath, "PSF or BDF bitmap font, drawn without antialiasing at the integer scale closest to the font size", None)
                 .named("custom-theme-fg", SyntaxShape::String, "custom foreground color in hex format (0x040404)", None)
                 .named("custom-theme-bg", SyntaxShape::String, "custom background color in hex format (0x040404)", None)
                 .named("custom-theme-black", SyntaxShape::String, "custom black color in hex format (0x040404)", None)
@@ -169,9 +180,11 @@ impl SimplePluginCommand for ToPngCommand {
     }
 }
 
+/// Font flags take a path, or a record describing a face of the file.
+fn font_shape() -> SyntaxShape {
+    SyntaxShape::OneOf(vec![SyntaxShape::Filepath, SyntaxShape::Record(vec![])])
+}
+
 fn main() {
-    nu_plugin::serve_plugin(
-        &mut ImageConversionPlugin {},
-        nu_plugin::MsgPackSerializer {},
-    )
+    nu_plugin::serve_plugin(&ImageConversionPlugin {}, nu_plugin::MsgPackSerializer {})
 }